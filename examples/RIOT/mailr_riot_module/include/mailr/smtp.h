@@ -7,17 +7,16 @@
 #include <stdint.h>
 #include <stdlib.h>
 
-#define SMTP_SESSION_UNCONNECTED {0}
+/* Must match `SMTP_SESSION_SIZE` and `SMTP_SESSION_ALIGN` in `ffi.rs`, which are checked against
+ * the actual session at compile time. */
+#define SMTP_SESSION_SIZE 384
+#define SMTP_SESSION_ALIGN 8
 
-typedef struct smtp_ehlo_info_t {
-    uint8_t extensions;
-} smtp_ehlo_info_t;
+#define SMTP_SESSION_UNCONNECTED {0}
 
+/* Opaque storage for a session. Initialize with SMTP_SESSION_UNCONNECTED. */
 typedef struct smtp_session_t {
-    sock_tcp_t *sock;
-    uint8_t *buffer;
-    size_t buffer_len;
-    smtp_ehlo_info_t ehlo_info;
+    _Alignas(SMTP_SESSION_ALIGN) uint8_t opaque[SMTP_SESSION_SIZE];
 } smtp_session_t;
 
 typedef struct smtp_auth_credential_t {
@@ -36,6 +36,8 @@ impl From<ConnectError<TcpNumericError>> for TcpNumericError {
             ConnectError::AuthFailed => riot_sys::EACCES,
             ConnectError::AuthUnsupported => riot_sys::EOPNOTSUPP,
             ConnectError::UnexpectedResponse => riot_sys::EPROTO,
+            ConnectError::GreetingRejected(_) => riot_sys::ECONNREFUSED,
+            ConnectError::InvalidInput(_) => riot_sys::EINVAL,
         };
         NumericError::from_constant(err as _).into()
     }
@@ -48,6 +50,9 @@ impl From<SendError<TcpNumericError>> for TcpNumericError {
             SendError::NoMem => riot_sys::ENOBUFS,
             SendError::SendFailed => riot_sys::EPROTO,
             SendError::UnexpectedResponse => riot_sys::EPROTO,
+            SendError::InvalidInput(_) => riot_sys::EINVAL,
+            SendError::MessageFailed => riot_sys::EIO,
+            SendError::Poisoned => riot_sys::ENOTCONN,
         };
         NumericError::from_constant(err as _).into()
     }
@@ -8,8 +8,8 @@ use core::{
 
 use mailr_nal::{
     auth::Credential,
-    message::{Envelope, Mail, Mailbox},
-    smtp::{ClientId, SmtpClient, SmtpClientSession},
+    message::{Body, Envelope, Headers, Mail, Mailbox},
+    smtp::{ClientId, SmtpClient, TcpSession},
 };
 
 use crate::{
@@ -59,7 +59,30 @@ fn ffi_to_str(value: *const ffi::c_char) -> Option<Result<&'static str, Utf8Erro
     }
 }
 
-pub type smtp_session_t<'a> = SmtpClientSession<'a, SingleSockTcpStack, FFISlice<u8>>;
+/// Size of `smtp_session_t`, which must match `SMTP_SESSION_SIZE` in `smtp.h`.
+pub const SMTP_SESSION_SIZE: usize = 384;
+/// Alignment of `smtp_session_t`, which must match `SMTP_SESSION_ALIGN` in `smtp.h`.
+pub const SMTP_SESSION_ALIGN: usize = 8;
+
+type Session = TcpSession<'static, SingleSockTcpStack, FFISlice<u8>>;
+
+/// Storage for a session, opaque to C. All zeros (`SMTP_SESSION_UNCONNECTED`) while not connected.
+#[repr(C, align(8))]
+pub struct smtp_session_t {
+    connected: bool,
+    session: MaybeUninit<Session>,
+}
+
+const _: () = assert!(core::mem::size_of::<smtp_session_t>() <= SMTP_SESSION_SIZE);
+const _: () = assert!(core::mem::align_of::<smtp_session_t>() == SMTP_SESSION_ALIGN);
+
+impl smtp_session_t {
+    fn get(&mut self) -> Option<&mut Session> {
+        // SAFETY: `session` is initialized whenever `connected` is set
+        self.connected
+            .then(|| unsafe { self.session.assume_init_mut() })
+    }
+}
 
 #[repr(C)]
 pub struct smtp_auth_credential_t {
@@ -92,11 +115,11 @@ pub unsafe extern "C" fn smtp_connect(
     session: *mut smtp_session_t,
     info: *mut smtp_connect_info_t,
 ) -> ffi::c_int {
-    if session.is_null() {
+    let Some(session) = session.as_mut() else {
         return riot_wrappers::error::EINVAL.number() as _;
-    }
+    };
 
-    if !(*(session as *const *const ffi::c_void)).is_null() {
+    if session.connected {
         return -(riot_sys::EISCONN as ffi::c_int);
     }
 
@@ -123,22 +146,23 @@ pub unsafe extern "C" fn smtp_connect(
 
     let client = try_riot!(result.map_err(TcpNumericError::from));
 
-    session.write(client);
+    session.session.write(client);
+    session.connected = true;
     0
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn smtp_close(session: *mut smtp_session_t) -> ffi::c_int {
-    if session.is_null() {
+    let Some(session) = session.as_mut() else {
         return riot_wrappers::error::EINVAL.number() as _;
-    }
-    if (*(session as *const *const ffi::c_void)).is_null() {
+    };
+
+    if !session.connected {
         return -(riot_sys::ENOTCONN as ffi::c_int);
     }
 
-    try_riot!(session
-        .replace(core::mem::zeroed::<MaybeUninit<_>>().assume_init())
-        .close());
+    session.connected = false;
+    try_riot!(session.session.assume_init_read().close());
     0
 }
 
@@ -182,9 +206,9 @@ pub unsafe extern "C" fn smtp_send(
         return riot_wrappers::error::EINVAL.number() as _;
     };
 
-    if core::mem::transmute_copy::<_, *const ffi::c_void>(session).is_null() {
+    let Some(session) = session.get() else {
         return -(riot_sys::ENOTCONN as ffi::c_int);
-    }
+    };
 
     let mail = {
         let Some(mailr_message_t {
@@ -207,7 +231,8 @@ pub unsafe extern "C" fn smtp_send(
             cc: cc.as_ref().iter().filter_map(into_mailbox),
             bcc: bcc.as_ref().iter().filter_map(into_mailbox),
             subject: ffi_to_str(*subject).and_then(Result::ok),
-            body: ffi_to_str(*body).and_then(Result::ok),
+            headers: Headers::default(),
+            body: ffi_to_str(*body).and_then(Result::ok).map(Body::Text),
         }
     };
 
@@ -231,9 +256,9 @@ pub unsafe extern "C" fn smtp_send_raw(
         return riot_wrappers::error::EINVAL.number() as _;
     };
 
-    if core::mem::transmute_copy::<_, *const ffi::c_void>(session).is_null() {
+    let Some(session) = session.get() else {
         return -(riot_sys::ENOTCONN as ffi::c_int);
-    }
+    };
 
     let Some(envelope) = envelope.as_ref() else {
         return riot_wrappers::error::EINVAL.number() as _;
@@ -68,6 +68,7 @@ impl From<SocketAddrWrapper> for SocketAddr {
     }
 }
 
+#[repr(transparent)]
 pub struct SingleSockTcpStack(riot_sys::sock_tcp_t);
 
 impl TcpClientStack for SingleSockTcpStack {
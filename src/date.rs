@@ -0,0 +1,268 @@
+use core::fmt;
+
+use crate::message::InputError;
+
+const SECS_PER_DAY: i64 = 86_400;
+
+/// Offsets from UTC must be less than a day, in minutes.
+const MAX_OFFSET_MINUTES: u16 = 24 * 60 - 1;
+
+/// Local times from 0000-01-01 00:00:00 to 9999-12-31 23:59:59, as seconds since the Unix epoch,
+/// so the year has 4 digits.
+const LOCAL_TIME_RANGE: core::ops::RangeInclusive<i64> = -62_167_219_200..=253_402_300_799;
+
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// A point in time, as seconds since the Unix epoch, and the offset from UTC of the local time it
+/// is displayed in.
+///
+/// Displayed as an RFC 5322 date-time (https://www.rfc-editor.org/rfc/rfc5322#section-3.3),
+/// e.g., `Fri, 16 Oct 2026 09:12:01 +0200`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DateTime {
+    pub timestamp: i64,
+    /// Offset from UTC in minutes, e.g., `120` for `+0200`.
+    pub offset_minutes: i16,
+}
+
+impl DateTime {
+    pub fn new(timestamp: i64, offset_minutes: i16) -> Self {
+        Self {
+            timestamp,
+            offset_minutes,
+        }
+    }
+
+    pub fn utc(timestamp: i64) -> Self {
+        Self::new(timestamp, 0)
+    }
+
+    /// Check the offset is less than a day, and the local time is within the years 0 to 9999, so
+    /// it's displayed as a valid date-time. Others are displayed clamped to a valid range instead.
+    pub fn validate(&self) -> Result<(), InputError> {
+        if self.offset_minutes.unsigned_abs() <= MAX_OFFSET_MINUTES
+            && LOCAL_TIME_RANGE.contains(&self.local_time())
+        {
+            Ok(())
+        } else {
+            Err(InputError::Date)
+        }
+    }
+
+    /// Seconds since the Unix epoch in local time, saturating at the bounds of `i64`.
+    fn local_time(&self) -> i64 {
+        self.timestamp
+            .saturating_add(i64::from(self.offset_minutes) * 60)
+    }
+}
+
+/// Turn days since the Unix epoch into a (year, month, day) date of the proleptic Gregorian
+/// calendar (https://howardhinnant.github.io/date_algorithms.html#civil_from_days).
+fn civil_from_days(days: i64) -> (i64, u8, u8) {
+    // shift the epoch to 0000-03-01, so leap days are at the end of the 400 year eras
+    let days = days.saturating_add(719_468);
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    // months starting from March
+    let month = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month + 2) / 5 + 1;
+    let month = if month < 10 { month + 3 } else { month - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month as u8, day as u8)
+}
+
+impl fmt::Display for DateTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // keep invalid dates (see `validate`) from overflowing
+        let local = self
+            .local_time()
+            .clamp(*LOCAL_TIME_RANGE.start(), *LOCAL_TIME_RANGE.end());
+        let days = local.div_euclid(SECS_PER_DAY);
+        let secs = local.rem_euclid(SECS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        // 1970-01-01 was a Thursday
+        let weekday = (days + 3).rem_euclid(7) as usize;
+
+        let sign = if self.offset_minutes < 0 { '-' } else { '+' };
+        let offset = self.offset_minutes.unsigned_abs().min(MAX_OFFSET_MINUTES);
+
+        write!(
+            f,
+            "{}, {:02} {} {:04} {:02}:{:02}:{:02} {}{:02}{:02}",
+            WEEKDAYS[weekday],
+            day,
+            MONTHS[usize::from(month) - 1],
+            year,
+            secs / 3600,
+            secs / 60 % 60,
+            secs % 60,
+            sign,
+            offset / 60,
+            offset % 60,
+        )
+    }
+}
+
+/// Source of the current time, e.g., an RTC, for the `Date` header of mails.
+pub trait Clock {
+    fn now(&self) -> DateTime;
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn format(timestamp: i64, offset_minutes: i16) -> String {
+        DateTime::new(timestamp, offset_minutes).to_string()
+    }
+
+    #[test]
+    fn examples() {
+        assert_eq!(format(0, 0), "Thu, 01 Jan 1970 00:00:00 +0000");
+        assert_eq!(format(-1, 0), "Wed, 31 Dec 1969 23:59:59 +0000");
+        assert_eq!(
+            format(1_792_134_721, 120),
+            "Fri, 16 Oct 2026 09:12:01 +0200"
+        );
+        assert_eq!(
+            format(1_792_134_721, -570),
+            "Thu, 15 Oct 2026 21:42:01 -0930"
+        );
+        assert_eq!(format(951_782_400, 0), "Tue, 29 Feb 2000 00:00:00 +0000");
+        assert_eq!(format(4_107_542_400, 0), "Mon, 01 Mar 2100 00:00:00 +0000");
+        assert_eq!(format(-2_203_891_200, 0), "Thu, 01 Mar 1900 00:00:00 +0000");
+    }
+
+    #[test]
+    fn year_2038() {
+        let max = i64::from(i32::MAX);
+        assert_eq!(format(max, 0), "Tue, 19 Jan 2038 03:14:07 +0000");
+        assert_eq!(format(max + 1, 0), "Tue, 19 Jan 2038 03:14:08 +0000");
+        assert_eq!(format(max + 1, 60), "Tue, 19 Jan 2038 04:14:08 +0100");
+        assert_eq!(
+            format(i64::from(u32::MAX), 0),
+            "Sun, 07 Feb 2106 06:28:15 +0000"
+        );
+        assert_eq!(
+            format(i64::from(i32::MIN), 0),
+            "Fri, 13 Dec 1901 20:45:52 +0000"
+        );
+    }
+
+    fn is_leap(year: i64) -> bool {
+        year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+    }
+
+    /// Every day from 1600 to 2500, checked against counting days one by one.
+    #[test]
+    fn every_day() {
+        let days_in_month = |year, month| match month {
+            2 if is_leap(year) => 29,
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        };
+
+        // 1600-01-01, a Saturday
+        let mut days = -135_140;
+        let mut weekday = 5;
+        for year in 1600..2500 {
+            for month in 1..=12 {
+                for day in 1..=days_in_month(year, month) {
+                    assert_eq!(civil_from_days(days), (year, month, day));
+
+                    let date = DateTime::utc(days * SECS_PER_DAY + SECS_PER_DAY - 1).to_string();
+                    assert_eq!(
+                        date,
+                        format!(
+                            "{}, {:02} {} {} 23:59:59 +0000",
+                            WEEKDAYS[weekday],
+                            day,
+                            MONTHS[usize::from(month) - 1],
+                            year
+                        )
+                    );
+
+                    days += 1;
+                    weekday = (weekday + 1) % 7;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn validate() {
+        let valid = [
+            DateTime::utc(0),
+            DateTime::new(0, 23 * 60 + 59),
+            DateTime::new(0, -(23 * 60 + 59)),
+            DateTime::utc(*LOCAL_TIME_RANGE.start()),
+            DateTime::utc(*LOCAL_TIME_RANGE.end()),
+            // the local time is what must be within the range
+            DateTime::new(LOCAL_TIME_RANGE.end() + 3600, -60),
+        ];
+        for date in valid {
+            assert_eq!(date.validate(), Ok(()), "{date:?}");
+        }
+        assert_eq!(
+            format(*LOCAL_TIME_RANGE.start(), 0),
+            "Sat, 01 Jan 0000 00:00:00 +0000"
+        );
+        assert_eq!(
+            format(*LOCAL_TIME_RANGE.end(), 0),
+            "Fri, 31 Dec 9999 23:59:59 +0000"
+        );
+
+        let invalid = [
+            DateTime::new(0, 24 * 60),
+            DateTime::new(0, -24 * 60),
+            DateTime::new(0, i16::MIN),
+            DateTime::utc(LOCAL_TIME_RANGE.start() - 1),
+            DateTime::utc(LOCAL_TIME_RANGE.end() + 1),
+            DateTime::new(*LOCAL_TIME_RANGE.end(), 1),
+            DateTime::new(i64::MAX, i16::MAX),
+            DateTime::new(i64::MIN, i16::MIN),
+        ];
+        for date in invalid {
+            assert_eq!(date.validate(), Err(InputError::Date), "{date:?}");
+        }
+    }
+
+    #[test]
+    fn invalid_dates_are_clamped() {
+        assert_eq!(
+            format(i64::MAX, i16::MAX),
+            "Fri, 31 Dec 9999 23:59:59 +2359"
+        );
+        assert_eq!(
+            format(i64::MIN, i16::MIN),
+            "Sat, 01 Jan 0000 00:00:00 -2359"
+        );
+        // doesn't overflow either
+        civil_from_days(i64::MAX);
+        civil_from_days(i64::MIN);
+    }
+
+    #[test]
+    fn offsets_across_days() {
+        // 2024-02-29 is a leap day
+        let timestamp = 1_709_164_800;
+        assert_eq!(format(timestamp, -1), "Wed, 28 Feb 2024 23:59:00 -0001");
+        assert_eq!(format(timestamp - 1, 1), "Thu, 29 Feb 2024 00:00:59 +0001");
+        assert_eq!(
+            format(timestamp + 86_399, 14 * 60),
+            "Fri, 01 Mar 2024 13:59:59 +1400"
+        );
+        assert_eq!(
+            format(timestamp, -12 * 60),
+            "Wed, 28 Feb 2024 12:00:00 -1200"
+        );
+    }
+}
@@ -0,0 +1,545 @@
+use core::fmt::Debug;
+
+use embedded_nal::{
+    nb::{self, block},
+    SocketAddr, UdpClientStack,
+};
+use heapless::{String, Vec};
+
+use crate::date::Clock;
+
+/// Maximum length of a domain name in text form (https://www.rfc-editor.org/rfc/rfc1035#section-2.3.4).
+pub const MAX_NAME_LEN: usize = 253;
+
+/// Maximum size of a DNS message carried over UDP (https://www.rfc-editor.org/rfc/rfc1035#section-4.2.1).
+/// A buffer of this size is always large enough for `MxResolver::resolve`.
+pub const MAX_UDP_MESSAGE_LEN: usize = 512;
+
+const HEADER_LEN: usize = 12;
+const TYPE_MX: u16 = 15;
+const CLASS_IN: u16 = 1;
+
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_TRUNCATED: u16 = 0x0200;
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+const RCODE_MASK: u16 = 0x000f;
+const RCODE_NXDOMAIN: u8 = 3;
+
+const DEFAULT_TIMEOUT_SECS: i64 = 5;
+const DEFAULT_RETRIES: u8 = 2;
+
+/// A mail exchanger of a domain (https://www.rfc-editor.org/rfc/rfc1035#section-3.3.9).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MxRecord {
+    /// Lower values are preferred.
+    pub preference: u16,
+    /// Hostname of the mail exchanger, without the trailing dot. Empty for a "null MX"
+    /// (https://www.rfc-editor.org/rfc/rfc7505), meaning the domain accepts no mail.
+    pub exchange: String<MAX_NAME_LEN>,
+}
+
+/// Resolves MX records by querying a recursive DNS server over UDP.
+pub struct MxResolver<'a, U>
+where
+    U: UdpClientStack,
+{
+    stack: &'a mut U,
+    server: SocketAddr,
+    next_id: u16,
+    clock: Option<&'a dyn Clock>,
+    timeout_secs: i64,
+    retries: u8,
+}
+
+impl<'a, U> MxResolver<'a, U>
+where
+    U: UdpClientStack,
+{
+    pub fn new(stack: &'a mut U, server: impl Into<SocketAddr>) -> Self {
+        Self {
+            stack,
+            server: server.into(),
+            next_id: 0,
+            clock: None,
+            timeout_secs: DEFAULT_TIMEOUT_SECS,
+            retries: DEFAULT_RETRIES,
+        }
+    }
+
+    /// Give up waiting for a response after `secs` seconds (5 by default), as measured by `clock`,
+    /// and send the query again (see `with_retries`). Without a clock, `resolve` waits for a
+    /// response forever.
+    pub fn with_timeout(mut self, clock: &'a dyn Clock, secs: u32) -> Self {
+        self.clock = Some(clock);
+        self.timeout_secs = secs.into();
+        self
+    }
+
+    /// Number of times a query is sent again after timing out, before failing with
+    /// `ResolveError::Timeout`. Defaults to 2.
+    pub fn with_retries(mut self, value: u8) -> Self {
+        self.retries = value;
+        self
+    }
+
+    /// Set the ID of the next query. Subsequent queries increment it. IDs should be unpredictable
+    /// (e.g., seeded from an RNG) to make spoofed responses harder.
+    pub fn with_query_id(mut self, value: u16) -> Self {
+        self.next_id = value;
+        self
+    }
+
+    /// Resolve the MX records of `domain`, using `buffer` for the DNS messages. Records are ordered
+    /// by preference. If the domain has more than `N` records, only the `N` most preferred are kept.
+    ///
+    /// An empty list means the domain exists but has no MX records, in which case the domain itself
+    /// should be used as the mail exchanger (https://www.rfc-editor.org/rfc/rfc5321#section-5.1).
+    // FIXME: Blocking for simplicity
+    pub fn resolve<const N: usize>(
+        &mut self,
+        domain: &str,
+        buffer: &mut [u8],
+    ) -> Result<Vec<MxRecord, N>, ResolveError<U::Error>> {
+        let id = self.next_id;
+        self.next_id = self.next_id.wrapping_add(1);
+
+        let query_len = encode_query(id, domain, buffer)?;
+
+        let mut socket = self.stack.socket().map_err(ResolveError::IoError)?;
+        let result = self.exchange(&mut socket, id, domain, buffer, query_len);
+        let _ = self.stack.close(socket);
+
+        let response_len = result?;
+        parse_response(id, &buffer[..response_len])
+    }
+
+    /// Send the query for `domain` in `buffer[..query_len]` and wait for its response, which is
+    /// read into `buffer`, sending the query again on timeout. Returns the response length.
+    fn exchange(
+        &mut self,
+        socket: &mut U::UdpSocket,
+        id: u16,
+        domain: &str,
+        buffer: &mut [u8],
+        query_len: usize,
+    ) -> Result<usize, ResolveError<U::Error>> {
+        self.stack
+            .connect(socket, self.server)
+            .map_err(ResolveError::IoError)?;
+
+        for attempt in 0..=self.retries {
+            if attempt > 0 {
+                // overwritten by anything received in the meantime
+                encode_query(id, domain, buffer)?;
+            }
+            block!(self.stack.send(socket, &buffer[..query_len])).map_err(ResolveError::IoError)?;
+
+            let deadline = self.clock.map(|clock| {
+                let deadline = clock.now().timestamp.saturating_add(self.timeout_secs);
+                (clock, deadline)
+            });
+
+            loop {
+                match self.stack.receive(socket, buffer) {
+                    // ignore anything that is not the answer to our query
+                    Ok((n, remote)) => {
+                        if remote == self.server && n >= 2 && buffer[..2] == id.to_be_bytes() {
+                            return Ok(n);
+                        }
+                    }
+                    Err(nb::Error::WouldBlock) => {
+                        if deadline
+                            .is_some_and(|(clock, deadline)| clock.now().timestamp >= deadline)
+                        {
+                            break;
+                        }
+                    }
+                    Err(nb::Error::Other(e)) => return Err(ResolveError::IoError(e)),
+                }
+            }
+        }
+
+        Err(ResolveError::Timeout)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ResolveError<E>
+where
+    E: Debug,
+{
+    IoError(E),
+    NoMem,
+    InvalidName,
+    FormatError,
+    /// The domain does not exist (NXDOMAIN).
+    NoSuchDomain,
+    /// The server answered with the given error RCODE (https://www.rfc-editor.org/rfc/rfc1035#section-4.1.1).
+    ServerFailure(u8),
+    /// No response was received after every retry (see `MxResolver::with_timeout`).
+    Timeout,
+}
+
+/// Write a recursive MX query for `domain` into `buffer`, returning the query length.
+fn encode_query<E: Debug>(
+    id: u16,
+    domain: &str,
+    buffer: &mut [u8],
+) -> Result<usize, ResolveError<E>> {
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+    if domain.is_empty() || domain.len() > MAX_NAME_LEN {
+        return Err(ResolveError::InvalidName);
+    }
+
+    // header + labels (each length-prefixed, which the dots account for) + root label + type + class
+    let len = HEADER_LEN + domain.len() + 2 + 4;
+    let buffer = buffer.get_mut(..len).ok_or(ResolveError::NoMem)?;
+
+    buffer[..HEADER_LEN].fill(0);
+    buffer[0..2].copy_from_slice(&id.to_be_bytes());
+    buffer[2..4].copy_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    buffer[4..6].copy_from_slice(&1u16.to_be_bytes()); // QDCOUNT
+
+    let mut pos = HEADER_LEN;
+    for label in domain.split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(ResolveError::InvalidName);
+        }
+        buffer[pos] = label.len() as u8;
+        buffer[pos + 1..pos + 1 + label.len()].copy_from_slice(label.as_bytes());
+        pos += 1 + label.len();
+    }
+    buffer[pos] = 0;
+    pos += 1;
+
+    buffer[pos..pos + 2].copy_from_slice(&TYPE_MX.to_be_bytes());
+    buffer[pos + 2..pos + 4].copy_from_slice(&CLASS_IN.to_be_bytes());
+
+    Ok(pos + 4)
+}
+
+fn parse_response<E: Debug, const N: usize>(
+    id: u16,
+    msg: &[u8],
+) -> Result<Vec<MxRecord, N>, ResolveError<E>> {
+    if msg.len() < HEADER_LEN || read_u16(msg, 0) != Some(id) {
+        return Err(ResolveError::FormatError);
+    }
+
+    let flags = read_u16(msg, 2).ok_or(ResolveError::FormatError)?;
+    if flags & FLAG_RESPONSE == 0 {
+        return Err(ResolveError::FormatError);
+    }
+
+    match (flags & RCODE_MASK) as u8 {
+        0 => {}
+        RCODE_NXDOMAIN => return Err(ResolveError::NoSuchDomain),
+        rcode => return Err(ResolveError::ServerFailure(rcode)),
+    }
+
+    let truncated = flags & FLAG_TRUNCATED != 0;
+    let question_count = read_u16(msg, 4).ok_or(ResolveError::FormatError)?;
+    let answer_count = read_u16(msg, 6).ok_or(ResolveError::FormatError)?;
+
+    let mut records = Vec::new();
+
+    let mut pos = HEADER_LEN;
+    for _ in 0..question_count {
+        pos = skip_name(msg, pos).ok_or(ResolveError::FormatError)? + 4;
+    }
+
+    for _ in 0..answer_count {
+        let Some(record) = parse_answer(msg, &mut pos) else {
+            if truncated {
+                // keep whatever fit into the truncated response
+                break;
+            }
+            return Err(ResolveError::FormatError);
+        };
+
+        if let Some(record) = record {
+            insert_by_preference(&mut records, record);
+        }
+    }
+
+    Ok(records)
+}
+
+/// Parse the resource record at `pos` and advance past it. Returns `Some(None)` for records that
+/// are not MX records, and `None` if the record is malformed.
+fn parse_answer(msg: &[u8], pos: &mut usize) -> Option<Option<MxRecord>> {
+    let start = skip_name(msg, *pos)?;
+    let rtype = read_u16(msg, start)?;
+    let class = read_u16(msg, start + 2)?;
+    let data_len = read_u16(msg, start + 8)? as usize;
+    let data = start + 10;
+
+    if data + data_len > msg.len() {
+        return None;
+    }
+    *pos = data + data_len;
+
+    if rtype != TYPE_MX || class != CLASS_IN {
+        return Some(None);
+    }
+
+    let preference = read_u16(msg, data)?;
+    let exchange = read_name(msg, data + 2)?;
+
+    Some(Some(MxRecord {
+        preference,
+        exchange,
+    }))
+}
+
+fn insert_by_preference<const N: usize>(records: &mut Vec<MxRecord, N>, record: MxRecord) {
+    let pos = records
+        .iter()
+        .position(|r| r.preference > record.preference)
+        .unwrap_or(records.len());
+
+    if pos >= N {
+        return;
+    }
+    if records.is_full() {
+        records.pop();
+    }
+    let _ = records.insert(pos, record);
+}
+
+fn read_u16(msg: &[u8], pos: usize) -> Option<u16> {
+    let bytes = msg.get(pos..pos + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Return the position right after the (possibly compressed) name at `pos`.
+fn skip_name(msg: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = *msg.get(pos)?;
+        match len {
+            0 => return Some(pos + 1),
+            // compression pointer, which always ends the name
+            len if len & 0xc0 == 0xc0 => return Some(pos + 2),
+            len if len & 0xc0 == 0 => pos += 1 + len as usize,
+            _ => return None,
+        }
+    }
+}
+
+/// Decode the (possibly compressed) name at `pos` (https://www.rfc-editor.org/rfc/rfc1035#section-4.1.4).
+fn read_name(msg: &[u8], mut pos: usize) -> Option<String<MAX_NAME_LEN>> {
+    let mut name = String::new();
+
+    // Pointers must point backwards, which rules out loops.
+    let mut limit = pos;
+
+    loop {
+        let len = *msg.get(pos)?;
+        match len {
+            0 => return Some(name),
+            len if len & 0xc0 == 0xc0 => {
+                let target = (read_u16(msg, pos)? & 0x3fff) as usize;
+                if target >= limit {
+                    return None;
+                }
+                pos = target;
+                limit = target;
+            }
+            len if len & 0xc0 == 0 => {
+                let label = msg.get(pos + 1..pos + 1 + len as usize)?;
+                let label = core::str::from_utf8(label).ok()?;
+
+                if !name.is_empty() {
+                    name.push('.').ok()?;
+                }
+                name.push_str(label).ok()?;
+                pos += 1 + len as usize;
+            }
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    type Error = ResolveError<()>;
+
+    /// Response to a query for `example.com` (ID 0x1234) with MX records `(preference, exchange)`,
+    /// where exchanges in `example.com` are compressed.
+    fn response(flags: u16, records: &[(u16, &str)]) -> std::vec::Vec<u8> {
+        let mut msg = std::vec::Vec::new();
+        msg.extend_from_slice(&0x1234u16.to_be_bytes());
+        msg.extend_from_slice(&(flags | FLAG_RESPONSE).to_be_bytes());
+        msg.extend_from_slice(&1u16.to_be_bytes());
+        msg.extend_from_slice(&(records.len() as u16).to_be_bytes());
+        msg.extend_from_slice(&[0, 0, 0, 0]);
+
+        // question: example.com MX IN
+        msg.extend_from_slice(b"\x07example\x03com\x00\x00\x0f\x00\x01");
+
+        for &(preference, exchange) in records {
+            let mut rdata = preference.to_be_bytes().to_vec();
+            match exchange.strip_suffix(".example.com") {
+                Some(host) => {
+                    for label in host.split('.') {
+                        rdata.push(label.len() as u8);
+                        rdata.extend_from_slice(label.as_bytes());
+                    }
+                    rdata.extend_from_slice(&[0xc0, 12]);
+                }
+                None => {
+                    for label in exchange.split('.').filter(|l| !l.is_empty()) {
+                        rdata.push(label.len() as u8);
+                        rdata.extend_from_slice(label.as_bytes());
+                    }
+                    rdata.push(0);
+                }
+            }
+
+            msg.extend_from_slice(&[0xc0, 12]); // owner name: pointer to question
+            msg.extend_from_slice(&TYPE_MX.to_be_bytes());
+            msg.extend_from_slice(&CLASS_IN.to_be_bytes());
+            msg.extend_from_slice(&300u32.to_be_bytes());
+            msg.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+            msg.extend_from_slice(&rdata);
+        }
+
+        msg
+    }
+
+    fn exchanges<const N: usize>(records: &Vec<MxRecord, N>) -> std::vec::Vec<(u16, &str)> {
+        records
+            .iter()
+            .map(|r| (r.preference, r.exchange.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn encode_mx_query() {
+        let mut buf = [0; MAX_UDP_MESSAGE_LEN];
+        let n = encode_query::<()>(0x1234, "example.com.", &mut buf).expect("encoded");
+
+        assert_eq!(
+            &buf[..n],
+            b"\x12\x34\x01\x00\x00\x01\x00\x00\x00\x00\x00\x00\x07example\x03com\x00\x00\x0f\x00\x01"
+        );
+    }
+
+    #[test]
+    fn encode_invalid_name() {
+        let mut buf = [0; MAX_UDP_MESSAGE_LEN];
+
+        for name in ["", ".", "example..com", &"a".repeat(64), &"a.".repeat(128)] {
+            assert_eq!(
+                encode_query::<()>(0, name, &mut buf),
+                Err(ResolveError::InvalidName),
+                "{name:?}"
+            );
+        }
+
+        assert_eq!(
+            encode_query::<()>(0, "example.com", &mut buf[..20]),
+            Err(ResolveError::NoMem)
+        );
+    }
+
+    #[test]
+    fn parse_sorted_by_preference() {
+        let msg = response(
+            0,
+            &[
+                (20, "mx2.example.com"),
+                (10, "mx1.example.com"),
+                (30, "backup.example.net"),
+                (10, "mx3.example.com"),
+            ],
+        );
+
+        let records = parse_response::<(), 8>(0x1234, &msg).expect("parsed");
+        assert_eq!(
+            exchanges(&records),
+            [
+                (10, "mx1.example.com"),
+                (10, "mx3.example.com"),
+                (20, "mx2.example.com"),
+                (30, "backup.example.net"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_keeps_most_preferred() {
+        let msg = response(
+            0,
+            &[
+                (30, "c.example.com"),
+                (10, "a.example.com"),
+                (20, "b.example.com"),
+            ],
+        );
+
+        let records = parse_response::<(), 2>(0x1234, &msg).expect("parsed");
+        assert_eq!(
+            exchanges(&records),
+            [(10, "a.example.com"), (20, "b.example.com")]
+        );
+    }
+
+    #[test]
+    fn parse_null_mx() {
+        let msg = response(0, &[(0, ".")]);
+
+        let records = parse_response::<(), 4>(0x1234, &msg).expect("parsed");
+        assert_eq!(exchanges(&records), [(0, "")]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        let msg = response(0, &[(10, "mx.example.com")]);
+        assert_eq!(
+            parse_response::<(), 4>(0x4321, &msg),
+            Err(Error::FormatError),
+            "ID mismatch"
+        );
+        assert_eq!(
+            parse_response::<(), 4>(0x1234, &msg[..msg.len() - 1]),
+            Err(Error::FormatError),
+            "cut short"
+        );
+
+        let msg = response(RCODE_NXDOMAIN as u16, &[]);
+        assert_eq!(
+            parse_response::<(), 4>(0x1234, &msg),
+            Err(Error::NoSuchDomain)
+        );
+
+        let msg = response(2, &[]);
+        assert_eq!(
+            parse_response::<(), 4>(0x1234, &msg),
+            Err(Error::ServerFailure(2))
+        );
+    }
+
+    #[test]
+    fn parse_truncated() {
+        let msg = response(
+            FLAG_TRUNCATED,
+            &[(10, "mx1.example.com"), (20, "mx2.example.com")],
+        );
+
+        let records = parse_response::<(), 4>(0x1234, &msg[..msg.len() - 1]).expect("parsed");
+        assert_eq!(exchanges(&records), [(10, "mx1.example.com")]);
+    }
+
+    #[test]
+    fn reject_pointer_loop() {
+        let mut msg = response(0, &[(10, "mx.example.com")]);
+        // make the question name point to itself
+        msg[HEADER_LEN] = 0xc0;
+        msg[HEADER_LEN + 1] = HEADER_LEN as u8;
+
+        assert_eq!(read_name(&msg, HEADER_LEN), None);
+    }
+}
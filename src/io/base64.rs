@@ -0,0 +1,208 @@
+//! Incremental base64 encoding (https://www.rfc-editor.org/rfc/rfc4648#section-4) straight into a
+//! `BufWriter`, without an intermediate buffer for the whole input.
+
+use base64::engine::{general_purpose::STANDARD as BASE64, Engine};
+
+use super::{BufWriter, Write};
+
+/// Bytes encoded at once, a multiple of 3 so no padding is produced in between.
+const CHUNK: usize = 48;
+
+/// Base64 encoder writing to a `BufWriter` as data is written to it, optionally wrapping lines
+/// (see `with_line_len`).
+///
+/// Up to 2 trailing bytes are held back until more data completes them, so `finish` must be
+/// called at the end to write them with padding. Dropping the encoder does it too, ignoring errors.
+pub struct Base64Writer<'w, 'a, W>
+where
+    W: Write,
+{
+    writer: &'w mut BufWriter<'a, W>,
+    pending: [u8; 3],
+    pending_len: usize,
+    line_len: Option<usize>,
+    /// Length of the current line, when wrapping lines
+    column: usize,
+}
+
+impl<'w, 'a, W> Base64Writer<'w, 'a, W>
+where
+    W: Write,
+{
+    pub fn new(writer: &'w mut BufWriter<'a, W>) -> Self {
+        Self {
+            writer,
+            pending: [0; 3],
+            pending_len: 0,
+            line_len: None,
+            column: 0,
+        }
+    }
+
+    /// Break the output with `\r\n` into lines of `len` characters, e.g., 76 for MIME
+    /// (https://www.rfc-editor.org/rfc/rfc2045#section-6.8). The last line isn't terminated.
+    /// Lines aren't wrapped if `len` is 0, the default.
+    pub fn with_line_len(mut self, len: usize) -> Self {
+        self.line_len = (len > 0).then_some(len);
+        self
+    }
+
+    /// Encode `input` as a continuation of everything written before, with no padding.
+    fn encode(&mut self, input: &[u8]) -> Result<(), W::Error> {
+        let mut encoded = [0; CHUNK / 3 * 4];
+        for chunk in input.chunks(CHUNK) {
+            let n = BASE64
+                .encode_slice(chunk, &mut encoded)
+                .expect("output fits a whole chunk");
+            self.put(&encoded[..n])?;
+        }
+        Ok(())
+    }
+
+    /// Write encoded output, wrapping lines.
+    fn put(&mut self, mut encoded: &[u8]) -> Result<(), W::Error> {
+        let Some(line_len) = self.line_len else {
+            return self.writer.write(encoded);
+        };
+
+        while !encoded.is_empty() {
+            // a line is only broken once there's more to write, so the last one isn't terminated
+            if self.column == line_len {
+                self.writer.write(b"\r\n")?;
+                self.column = 0;
+            }
+            let n = encoded.len().min(line_len - self.column);
+            self.writer.write(&encoded[..n])?;
+            self.column += n;
+            encoded = &encoded[n..];
+        }
+        Ok(())
+    }
+
+    // FIXME: Blocking for now for simplicity
+    pub fn write(&mut self, mut data: &[u8]) -> Result<(), W::Error> {
+        if self.pending_len > 0 {
+            let n = data.len().min(3 - self.pending_len);
+            self.pending[self.pending_len..self.pending_len + n].copy_from_slice(&data[..n]);
+            self.pending_len += n;
+            data = &data[n..];
+
+            if self.pending_len < 3 {
+                return Ok(());
+            }
+            let pending = self.pending;
+            self.pending_len = 0;
+            self.encode(&pending)?;
+        }
+
+        let whole = data.len() - data.len() % 3;
+        self.encode(&data[..whole])?;
+
+        let rest = &data[whole..];
+        self.pending[..rest.len()].copy_from_slice(rest);
+        self.pending_len = rest.len();
+        Ok(())
+    }
+
+    /// Write the held back bytes, padded.
+    fn write_pending(&mut self) -> Result<(), W::Error> {
+        let pending = self.pending;
+        let pending_len = core::mem::take(&mut self.pending_len);
+        self.encode(&pending[..pending_len])
+    }
+
+    pub fn finish(mut self) -> Result<(), W::Error> {
+        self.write_pending()
+    }
+}
+
+impl<W> Drop for Base64Writer<'_, '_, W>
+where
+    W: Write,
+{
+    fn drop(&mut self) {
+        let _ = self.write_pending();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::mock::Replay;
+
+    fn encode(data: &[u8], split: usize) -> Vec<u8> {
+        encode_lines(data, split, 0)
+    }
+
+    fn encode_lines(data: &[u8], split: usize, line_len: usize) -> Vec<u8> {
+        let mut sink = Replay::sink();
+        let mut buf = [0; 16];
+        let mut writer = BufWriter::new(&mut sink, &mut buf);
+        let mut encoder = Base64Writer::new(&mut writer).with_line_len(line_len);
+        for part in data.chunks(split) {
+            encoder.write(part).unwrap();
+        }
+        encoder.finish().unwrap();
+        drop(writer);
+        sink.written
+    }
+
+    #[test]
+    fn rfc4648_vectors() {
+        for (data, expected) in [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+        ] {
+            assert_eq!(encode(data.as_bytes(), 1), expected.as_bytes());
+            assert_eq!(encode(data.as_bytes(), 100), expected.as_bytes());
+        }
+    }
+
+    #[test]
+    fn any_split() {
+        let data: Vec<u8> = (0..=255).cycle().take(500).collect();
+        for len in [0, 1, 2, 3, 47, 48, 49, 100, 500] {
+            let mut expected = vec![0; 1000];
+            let n = BASE64.encode_slice(&data[..len], &mut expected).unwrap();
+            for split in 1..=50 {
+                assert_eq!(encode(&data[..len], split), &expected[..n]);
+            }
+        }
+    }
+
+    #[test]
+    fn lines() {
+        let data: Vec<u8> = (0..=255).cycle().take(500).collect();
+        for len in [0, 1, 2, 3, 56, 57, 58, 114, 115, 500] {
+            let mut expected = vec![0; 1000];
+            let n = BASE64.encode_slice(&data[..len], &mut expected).unwrap();
+            let expected = expected[..n]
+                .chunks(76)
+                .collect::<Vec<_>>()
+                .join(&b"\r\n"[..]);
+
+            for split in [1, 2, 3, 56, 57, 58, 100, 500] {
+                assert_eq!(
+                    encode_lines(&data[..len], split, 76),
+                    expected,
+                    "{len} by {split}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn finish_on_drop() {
+        let mut sink = Replay::sink();
+        let mut buf = [0; 16];
+        let mut writer = BufWriter::new(&mut sink, &mut buf);
+        Base64Writer::new(&mut writer).write(b"\0user").unwrap();
+        drop(writer);
+        assert_eq!(sink.written, b"AHVzZXI=");
+    }
+}
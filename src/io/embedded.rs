@@ -0,0 +1,254 @@
+//! Adapters between this crate's `Read`/`Write` traits and the `embedded-io` ones
+//! (https://docs.rs/embedded-io).
+
+use core::fmt::Debug;
+
+use embedded_nal::nb::{self, block};
+
+use super::{ErrorType, Read, Write};
+
+/// Use an `embedded_io` reader/writer (e.g., a serial port or a TLS stream) as a transport.
+#[derive(Debug)]
+pub struct FromEmbeddedIo<T>(pub T);
+
+impl<T> FromEmbeddedIo<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: embedded_io::ErrorType> ErrorType for FromEmbeddedIo<T> {
+    type Error = T::Error;
+}
+
+impl<T: embedded_io::Read> Read for FromEmbeddedIo<T> {
+    // FIXME: Blocking, as `embedded_io::Read` may block until some bytes are available
+    fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+        Ok(self.0.read(buffer)?)
+    }
+}
+
+impl<T: embedded_io::Write> Write for FromEmbeddedIo<T> {
+    // FIXME: Blocking, as `embedded_io::Write` may block until some bytes are written
+    fn write(&mut self, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+        Ok(self.0.write(buffer)?)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush()
+    }
+}
+
+/// Use this crate's reader/writer (e.g., a `TcpStream`) where `embedded_io` (or, with the
+/// `embedded-io-async` feature, `embedded_io_async`) traits are expected.
+#[derive(Debug)]
+pub struct ToEmbeddedIo<T>(pub T);
+
+impl<T> ToEmbeddedIo<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// Error of `ToEmbeddedIo`, wrapping the error of the inner reader/writer.
+#[derive(Debug, PartialEq, Eq)]
+pub struct EmbeddedIoError<E>(pub E);
+
+impl<E: Debug> embedded_io::Error for EmbeddedIoError<E> {
+    fn kind(&self) -> embedded_io::ErrorKind {
+        embedded_io::ErrorKind::Other
+    }
+}
+
+impl<T: ErrorType> embedded_io::ErrorType for ToEmbeddedIo<T> {
+    type Error = EmbeddedIoError<T::Error>;
+}
+
+impl<T: Read> embedded_io::Read for ToEmbeddedIo<T> {
+    // FIXME: Blocking for simplicity
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        block!(self.0.read(buf)).map_err(EmbeddedIoError)
+    }
+}
+
+impl<T: Write> embedded_io::Write for ToEmbeddedIo<T> {
+    // FIXME: Blocking for simplicity
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        block!(self.0.write(buf)).map_err(EmbeddedIoError)
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        self.0.flush().map_err(EmbeddedIoError)
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+mod asynch {
+    use core::{
+        future::{poll_fn, Future},
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use embedded_nal::nb;
+
+    use super::{EmbeddedIoError, ToEmbeddedIo};
+    use crate::io::{ErrorType, Read, Write};
+
+    /// Runs a future to completion from blocking code, e.g., the `block_on` of the executor
+    /// driving the async reader/writer.
+    pub trait BlockOn {
+        fn block_on<F: Future>(&mut self, future: F) -> F::Output;
+    }
+
+    /// Polls the future over and over with a waker doing nothing, until it completes.
+    ///
+    /// Only valid for futures that make progress without being woken (e.g., the ones of
+    /// `ToEmbeddedIo`). Others would spin forever, so use their executor instead.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct BusyPoll;
+
+    impl BlockOn for BusyPoll {
+        fn block_on<F: Future>(&mut self, future: F) -> F::Output {
+            let mut future = pin!(future);
+            let mut cx = Context::from_waker(Waker::noop());
+
+            loop {
+                if let Poll::Ready(output) = future.as_mut().poll(&mut cx) {
+                    return output;
+                }
+            }
+        }
+    }
+
+    /// Use an `embedded_io_async` reader/writer as a transport, running its futures to
+    /// completion with `X` (see `BlockOn`).
+    #[derive(Debug)]
+    pub struct FromEmbeddedIoAsync<T, X>(pub T, pub X)
+    where
+        X: BlockOn;
+
+    impl<T, X: BlockOn> FromEmbeddedIoAsync<T, X> {
+        pub fn into_inner(self) -> T {
+            self.0
+        }
+    }
+
+    impl<T: embedded_io_async::ErrorType, X: BlockOn> ErrorType for FromEmbeddedIoAsync<T, X> {
+        type Error = T::Error;
+    }
+
+    impl<T: embedded_io_async::Read, X: BlockOn> Read for FromEmbeddedIoAsync<T, X> {
+        // FIXME: Blocking, as the future can't be kept between calls without cancelling it
+        fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+            Ok(self.1.block_on(self.0.read(buffer))?)
+        }
+    }
+
+    impl<T: embedded_io_async::Write, X: BlockOn> Write for FromEmbeddedIoAsync<T, X> {
+        // FIXME: Blocking, as the future can't be kept between calls without cancelling it
+        fn write(&mut self, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+            Ok(self.1.block_on(self.0.write(buffer))?)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            self.1.block_on(self.0.flush())
+        }
+    }
+
+    /// Turn a non-blocking result into a poll, waking the task right away to be polled again.
+    fn poll_nb<O, E>(cx: &mut Context<'_>, result: nb::Result<O, E>) -> Poll<Result<O, E>> {
+        match result {
+            Err(nb::Error::WouldBlock) => {
+                cx.waker().wake_by_ref();
+                Poll::Pending
+            }
+            Err(nb::Error::Other(e)) => Poll::Ready(Err(e)),
+            Ok(o) => Poll::Ready(Ok(o)),
+        }
+    }
+
+    impl<T: Read> embedded_io_async::Read for ToEmbeddedIo<T> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            poll_fn(|cx| poll_nb(cx, self.0.read(buf)))
+                .await
+                .map_err(EmbeddedIoError)
+        }
+    }
+
+    impl<T: Write> embedded_io_async::Write for ToEmbeddedIo<T> {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            poll_fn(|cx| poll_nb(cx, self.0.write(buf)))
+                .await
+                .map_err(EmbeddedIoError)
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            self.0.flush().map_err(EmbeddedIoError)
+        }
+    }
+}
+
+#[cfg(feature = "embedded-io-async")]
+pub use asynch::{BlockOn, BusyPoll, FromEmbeddedIoAsync};
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::{mock::Flaky, BufReader, BufWriter};
+
+    #[test]
+    fn from_embedded_io() {
+        // `embedded_io` is implemented for byte slices
+        let mut reader = FromEmbeddedIo(&b"220 ready\r\n250 OK\r\n"[..]);
+        let (mut buf, mut filled) = ([0; 32], 0..0);
+        let mut reader = BufReader::new(&mut reader, &mut buf, &mut filled);
+        assert_eq!(reader.read_line(), Ok("220 ready"));
+        assert_eq!(reader.read_line(), Ok("250 OK"));
+
+        let mut out = [0; 16];
+        let mut writer = FromEmbeddedIo(&mut out[..]);
+        let mut buf = [0; 4];
+        BufWriter::new(&mut writer, &mut buf)
+            .write(b"QUIT\r\n")
+            .unwrap();
+        assert_eq!(&out[..6], b"QUIT\r\n");
+    }
+
+    #[test]
+    fn to_embedded_io() {
+        use embedded_io::{Read as _, Write as _};
+
+        let mut io = ToEmbeddedIo(Flaky::new(b"250 OK\r\n", 1));
+
+        let mut buf = [0; 8];
+        io.read_exact(&mut buf).unwrap();
+        assert_eq!(&buf, b"250 OK\r\n");
+        assert_eq!(io.read(&mut buf), Ok(0));
+
+        io.write_all(b"NOOP\r\n").unwrap();
+        io.flush().unwrap();
+        assert_eq!(io.into_inner().written, b"NOOP\r\n");
+    }
+
+    #[cfg(feature = "embedded-io-async")]
+    #[test]
+    fn embedded_io_async() {
+        use embedded_io_async::{Read as _, Write as _};
+
+        // from this crate's traits to the async ones, whose futures don't wait to be woken
+        let mut io = ToEmbeddedIo(Flaky::new(b"250 OK\r\n", 1));
+        let mut buf = [0; 8];
+        BusyPoll.block_on(io.read_exact(&mut buf)).unwrap();
+        assert_eq!(&buf, b"250 OK\r\n");
+        BusyPoll.block_on(io.write_all(b"NOOP\r\n")).unwrap();
+
+        // and back
+        let mut io = FromEmbeddedIoAsync(io, BusyPoll);
+        let mut buf = [0; 4];
+        BufWriter::new(&mut io, &mut buf)
+            .write(b"QUIT\r\n")
+            .unwrap();
+        assert_eq!(io.into_inner().into_inner().written, b"NOOP\r\nQUIT\r\n");
+    }
+}
@@ -0,0 +1,106 @@
+//! In-memory transports for tests.
+
+use core::convert::Infallible;
+
+use embedded_nal::nb;
+
+use super::{ErrorType, Read, Write};
+
+/// Transport replaying the server's replies, and recording what the client writes.
+pub(crate) struct Replay {
+    /// Replies not read yet
+    pub replies: Vec<u8>,
+    pub written: Vec<u8>,
+}
+
+impl Replay {
+    pub fn new(replies: &str) -> Self {
+        Self {
+            replies: replies.as_bytes().to_vec(),
+            written: Vec::new(),
+        }
+    }
+
+    /// A transport only recording what is written.
+    pub fn sink() -> Self {
+        Self::new("")
+    }
+}
+
+impl ErrorType for Replay {
+    type Error = Infallible;
+}
+
+impl Read for Replay {
+    fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+        // as much as fits, as if the server replied early, before the commands are received
+        let n = self.replies.len().min(buffer.len());
+        buffer[..n].copy_from_slice(&self.replies[..n]);
+        self.replies.drain(..n);
+        Ok(n)
+    }
+}
+
+impl Write for Replay {
+    fn write(&mut self, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+        self.written.extend_from_slice(buffer);
+        Ok(buffer.len())
+    }
+}
+
+/// Same as `Replay`, but would block and read or write short at pseudo-random points.
+pub(crate) struct Flaky {
+    pub replies: &'static [u8],
+    pub written: Vec<u8>,
+    state: u32,
+}
+
+impl Flaky {
+    /// `seed` must not be 0.
+    pub fn new(replies: &'static [u8], seed: u32) -> Self {
+        Self {
+            replies,
+            written: Vec::new(),
+            state: seed,
+        }
+    }
+
+    /// Next pseudo-random number (xorshift32).
+    pub fn next(&mut self) -> usize {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state as usize
+    }
+}
+
+impl ErrorType for Flaky {
+    type Error = Infallible;
+}
+
+impl Read for Flaky {
+    fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
+        if self.next() % 3 == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        let n = self
+            .replies
+            .len()
+            .min(buffer.len())
+            .min(1 + self.next() % 7);
+        buffer[..n].copy_from_slice(&self.replies[..n]);
+        self.replies = &self.replies[n..];
+        Ok(n)
+    }
+}
+
+impl Write for Flaky {
+    fn write(&mut self, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+        if self.next() % 3 == 0 {
+            return Err(nb::Error::WouldBlock);
+        }
+        let n = buffer.len().min(1 + self.next() % 7);
+        self.written.extend_from_slice(&buffer[..n]);
+        Ok(n)
+    }
+}
@@ -1,3 +1,5 @@
+use core::{fmt::Debug, ops::Range};
+
 mod read;
 pub use read::*;
 
@@ -7,25 +9,148 @@ pub use write::*;
 mod stream;
 pub use stream::*;
 
-#[repr(C)]
-pub struct WithBuf<T, B: AsMut<[u8]>>(pub T, pub B);
+mod base64;
+pub use self::base64::*;
+
+#[cfg(test)]
+pub(crate) mod mock;
+
+#[cfg(feature = "embedded-io")]
+mod embedded;
+#[cfg(feature = "embedded-io")]
+pub use embedded::*;
+
+/// The error type shared by the `Read` and `Write` halves of a transport.
+pub trait ErrorType {
+    type Error: Debug;
+}
 
-impl<'a, R, B> From<&'a mut WithBuf<R, B>> for BufReader<'a, R>
+/// A stream with a buffer shared for reading and writing. The read-ahead is kept between readers
+/// (see `reader`), and writers use the rest of the buffer.
+#[repr(C)]
+pub struct BufStream<S, B>
 where
-    R: Read,
     B: AsMut<[u8]>,
 {
-    fn from(value: &'a mut WithBuf<R, B>) -> Self {
-        Self::new(&mut value.0, value.1.as_mut())
-    }
+    stream: S,
+    buffer: B,
+    /// The read-ahead, not consumed yet.
+    filled: Range<usize>,
 }
 
-impl<'a, R, B> From<&'a mut WithBuf<R, B>> for BufWriter<'a, R>
+impl<S, B> BufStream<S, B>
 where
-    R: Write,
     B: AsMut<[u8]>,
 {
-    fn from(value: &'a mut WithBuf<R, B>) -> Self {
-        Self::new(&mut value.0, value.1.as_mut())
+    pub fn new(stream: S, buffer: B) -> Self {
+        Self::from_parts(stream, buffer, 0..0)
+    }
+
+    /// Rebuild a stream from the parts of another one (see `into_parts`). `buffer` must hold the
+    /// same data as the one it was taken from.
+    pub(crate) fn from_parts(stream: S, buffer: B, filled: Range<usize>) -> Self {
+        Self {
+            stream,
+            buffer,
+            filled,
+        }
+    }
+
+    pub(crate) fn into_parts(self) -> (S, B, Range<usize>) {
+        (self.stream, self.buffer, self.filled)
+    }
+
+    /// A reader starting with the data left buffered by the previous one. It may use the whole
+    /// buffer, so writers must be flushed first.
+    pub fn reader(&mut self) -> BufReader<'_, S>
+    where
+        S: Read,
+    {
+        BufReader::new(&mut self.stream, self.buffer.as_mut(), &mut self.filled)
+    }
+
+    /// A writer using the part of the buffer after the read-ahead, which is usually empty.
+    pub fn writer(&mut self) -> BufWriter<'_, S>
+    where
+        S: Write,
+    {
+        let (stream, buffer) = self.write_parts();
+        BufWriter::new(stream, buffer)
+    }
+
+    /// A writer resuming one released with `BufWriter::into_pending`, with nothing read since.
+    pub(crate) fn writer_with_pending(&mut self, pending: Range<usize>) -> BufWriter<'_, S>
+    where
+        S: Write,
+    {
+        let (stream, buffer) = self.write_parts();
+        BufWriter::with_pending(stream, buffer, pending)
+    }
+
+    /// Move the read-ahead to the front of the buffer, and return the free part after it.
+    fn write_parts(&mut self) -> (&mut S, &mut [u8]) {
+        let buffer = self.buffer.as_mut();
+        buffer.copy_within(self.filled.clone(), 0);
+        self.filled = 0..self.filled.len();
+
+        (&mut self.stream, &mut buffer[self.filled.end..])
+    }
+
+    pub fn get_mut(&mut self) -> &mut S {
+        &mut self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{mock::Replay, *};
+
+    #[test]
+    fn read_ahead_is_kept() {
+        let replay = Replay::new("250 first\r\n250 second\r\n250 third\r\n");
+        let mut stream = BufStream::new(replay, [0; 128]);
+
+        assert_eq!(stream.reader().read_line(), Ok("250 first"));
+        // everything was read at once, and is kept buffered
+        assert!(stream.get_mut().replies.is_empty());
+
+        // writing doesn't touch the read-ahead
+        stream.writer().write(b"NOOP\r\n").unwrap();
+        assert_eq!(stream.get_mut().written, b"NOOP\r\n");
+
+        assert_eq!(stream.reader().read_line(), Ok("250 second"));
+        assert_eq!(stream.reader().read_line(), Ok("250 third"));
+        assert_eq!(stream.reader().read_line(), Ok(""));
+    }
+
+    #[test]
+    fn read_ahead_is_moved_to_front() {
+        let replay = Replay::new("250 0123456789\r\n250 abcdefghij\r\n");
+        // the second line only fits once the first is consumed
+        let mut stream = BufStream::new(replay, [0; 24]);
+
+        assert_eq!(stream.reader().read_line(), Ok("250 0123456789"));
+        assert_eq!(stream.reader().read_line(), Ok("250 abcdefghij"));
+    }
+
+    #[test]
+    fn whole_buffer_is_shared() {
+        let replay = Replay::new("250-a long reply line\r\n250 x\r\n");
+        let mut stream = BufStream::new(replay, [0; 24]);
+
+        // a line filling nearly the whole buffer can be read
+        assert_eq!(stream.reader().read_line(), Ok("250-a long reply line"));
+
+        // the writer buffers up to the read-ahead left
+        let mut writer = stream.writer();
+        writer.write(b"RSET\r\n").unwrap();
+        drop(writer);
+        assert_eq!(stream.get_mut().written, b"RSET\r\n");
+
+        assert_eq!(stream.reader().read_line(), Ok("250 x"));
     }
 }
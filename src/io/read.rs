@@ -2,9 +2,9 @@ use core::{fmt::Debug, ops::Range, str};
 
 use embedded_nal::nb::{self, block};
 
-pub trait Read {
-    type Error: Debug;
+use super::ErrorType;
 
+pub trait Read: ErrorType {
     fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error>;
 }
 
@@ -14,18 +14,20 @@ where
 {
     reader: &'a mut R,
     buf: &'a mut [u8],
-    filled: Range<usize>,
+    filled: &'a mut Range<usize>,
 }
 
 impl<'a, R> BufReader<'a, R>
 where
     R: Read,
 {
-    pub fn new(reader: &'a mut R, buf: &'a mut [u8]) -> Self {
+    /// `filled` is the part of `buf` already read from `reader` and not consumed yet (e.g., left
+    /// by a previous `BufReader`), and is kept up to date.
+    pub fn new(reader: &'a mut R, buf: &'a mut [u8], filled: &'a mut Range<usize>) -> Self {
         Self {
             reader,
             buf,
-            filled: 0..0,
+            filled,
         }
     }
 
@@ -33,8 +35,8 @@ where
     fn consume(&mut self, amt: usize) -> &[u8] {
         let consumed = &self.buf[self.filled.start..self.filled.start + amt];
         self.filled.start += amt;
-        if self.filled.is_empty() {
-            self.filled = 0..0
+        if (*self.filled).is_empty() {
+            *self.filled = 0..0
         }
         consumed
     }
@@ -52,67 +54,120 @@ where
         Ok(filled)
     }
 
-    /// Return a block of buffered data until the byte where predicate `p` returns true, or EOF is met,
-    /// reading from the Reader if there's not enough buffered bytes.
-    ///
-    /// If the buffer is full without having found the needed byte, `FullBuffer` error is returned with all
-    /// the buffered data up to that point returned.
+    /// Look for the byte where predicate `p` returns true, reading from the Reader until it's found, the
+    /// buffer is full or EOF is met.
     ///
-    // FIXME: Blocking for now for simplicity
-    pub fn read_until<P>(&mut self, p: P) -> Result<&[u8], BufReaderError<R::Error>>
+    /// Nothing is consumed, and everything read so far stays in the filled block of the buffer, so it
+    /// can be called again after `WouldBlock`.
+    fn poll_scan<P>(&mut self, p: P) -> nb::Result<Scan, R::Error>
     where
         P: FnMut(&u8) -> bool + Copy,
     {
         let mut checked_block_size = 0;
-        let mut unchecked_block = self.get_filled();
 
         loop {
-            if let Some(pos) = unchecked_block.iter().position(p) {
-                return Ok(self.consume(checked_block_size + pos + 1));
+            if let Some(pos) = self.get_filled()[checked_block_size..].iter().position(p) {
+                return Ok(Scan::Found(checked_block_size + pos + 1));
             }
 
             if self.filled.end >= self.buf.len() {
                 if self.filled.start == 0 {
-                    return Err(BufReaderError::FullBuffer(self.consume(self.filled.len())));
+                    return Ok(Scan::FullBuffer);
                 }
 
                 // We've filled until the end without finding what we need, but some bytes at the front
                 // of the buffer has been consumed (didn't fail with FullBuffer).
                 // Let's move the filled block to front of the buffer to attempt to fill the remaining space
                 self.buf.copy_within(self.filled.clone(), 0);
-                self.filled = 0..self.filled.len();
+                *self.filled = 0..self.filled.len();
             }
 
             checked_block_size = self.filled.len();
 
-            unchecked_block =
-                block!(self.fill_buf()).map_err(|e| BufReaderError::ReaderError(e))?;
-            if unchecked_block.is_empty() {
+            if self.fill_buf()?.is_empty() {
                 // because we moved the filled block to front earlier, leaving space to be filled,
                 // if we get nothing back after a read, EOF must have occurred.
-                return Ok(self.consume(checked_block_size));
+                return Ok(Scan::Eof);
             }
         }
     }
 
-    // FIXME: Blocking for now for simplicity
+    /// Consume the block found by `poll_scan`.
+    fn take(&mut self, scan: Scan) -> Result<&[u8], BufReaderError<'_, R::Error>> {
+        match scan {
+            Scan::Found(amt) => Ok(self.consume(amt)),
+            Scan::Eof => Ok(self.consume(self.filled.len())),
+            Scan::FullBuffer => Err(BufReaderError::FullBuffer(self.consume(self.filled.len()))),
+        }
+    }
+
+    /// Return a block of buffered data until the byte where predicate `p` returns true, or EOF is met,
+    /// reading from the Reader if there's not enough buffered bytes.
+    ///
+    /// If the buffer is full without having found the needed byte, `FullBuffer` error is returned with all
+    /// the buffered data up to that point returned.
+    ///
+    /// If the Reader would block, `WouldBlock` is returned and the bytes read so far are kept in the
+    /// buffer, to be returned by the next call.
+    pub fn poll_read_until<P>(&mut self, p: P) -> nb::Result<&[u8], BufReaderError<'_, R::Error>>
+    where
+        P: FnMut(&u8) -> bool + Copy,
+    {
+        let scan = self
+            .poll_scan(p)
+            .map_err(|e| e.map(BufReaderError::ReaderError))?;
+        self.take(scan).map_err(nb::Error::Other)
+    }
+
+    /// Blocking version of [`Self::poll_read_until`].
+    pub fn read_until<P>(&mut self, p: P) -> Result<&[u8], BufReaderError<'_, R::Error>>
+    where
+        P: FnMut(&u8) -> bool + Copy,
+    {
+        let scan = block!(self.poll_scan(p)).map_err(BufReaderError::ReaderError)?;
+        self.take(scan)
+    }
+
     #[inline]
-    pub fn read_str_until<P>(&mut self, p: P) -> Result<&str, BufReaderError<R::Error>>
+    pub fn poll_read_str_until<P>(&mut self, p: P) -> nb::Result<&str, BufReaderError<'_, R::Error>>
     where
         P: FnMut(&u8) -> bool + Copy,
     {
-        self.read_until(p).and_then(|data| {
-            str::from_utf8(data).map_err(|e| BufReaderError::DecodeFailed(data, e))
-        })
+        self.poll_read_until(p)
+            .and_then(|data| decode(data).map_err(nb::Error::Other))
     }
 
-    // FIXME: Blocking for now for simplicity
-    pub fn read_line(&mut self) -> Result<&str, BufReaderError<R::Error>> {
+    #[inline]
+    pub fn read_str_until<P>(&mut self, p: P) -> Result<&str, BufReaderError<'_, R::Error>>
+    where
+        P: FnMut(&u8) -> bool + Copy,
+    {
+        self.read_until(p).and_then(decode)
+    }
+
+    pub fn poll_read_line(&mut self) -> nb::Result<&str, BufReaderError<'_, R::Error>> {
+        self.poll_read_str_until(|&byte| byte == b'\n')
+            .map(|line| line.trim_end_matches("\r\n"))
+    }
+
+    pub fn read_line(&mut self) -> Result<&str, BufReaderError<'_, R::Error>> {
         self.read_str_until(|&byte| byte == b'\n')
             .map(|line| line.trim_end_matches("\r\n"))
     }
 }
 
+/// Outcome of `BufReader::poll_scan`.
+enum Scan {
+    /// The block to consume, up to and including the needed byte.
+    Found(usize),
+    FullBuffer,
+    Eof,
+}
+
+fn decode<E: Debug>(data: &[u8]) -> Result<&str, BufReaderError<'_, E>> {
+    str::from_utf8(data).map_err(|e| BufReaderError::DecodeFailed(data, e))
+}
+
 #[derive(Debug, PartialEq)]
 pub enum BufReaderError<'a, E>
 where
@@ -122,3 +177,66 @@ where
     ReaderError(E),
     DecodeFailed(&'a [u8], str::Utf8Error),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::mock::Flaky;
+
+    const DATA: &[u8] = b"250-mail.example.com\r\n250-PIPELINING\r\n250-SIZE 35882577\r\n\
+        250-8BITMIME\r\n250-AUTH LOGIN PLAIN\r\n250-ENHANCEDSTATUSCODES\r\n250 SMTPUTF8\r\nno newline";
+
+    #[test]
+    fn poll_read_line() {
+        let expected: Vec<&str> = str::from_utf8(DATA)
+            .unwrap()
+            .split('\n')
+            .map(|line| line.trim_end_matches('\r'))
+            .collect();
+
+        for seed in 1..100 {
+            let mut reader = Flaky::new(DATA, seed);
+            let (mut buf, mut filled) = ([0; 32], 0..0);
+            let mut reader = BufReader::new(&mut reader, &mut buf, &mut filled);
+
+            let mut lines = Vec::new();
+            let mut would_block = 0;
+            while lines.len() < expected.len() {
+                match reader.poll_read_line() {
+                    Ok(line) => lines.push(line.to_owned()),
+                    Err(nb::Error::WouldBlock) => would_block += 1,
+                    Err(nb::Error::Other(e)) => panic!("{e:?}"),
+                }
+            }
+            assert_eq!(lines, expected, "seed {seed}");
+            assert!(would_block > 0);
+            assert_eq!(block!(reader.poll_read_line()), Ok(""));
+        }
+    }
+
+    #[test]
+    fn poll_read_until_full_buffer() {
+        for seed in 1..100 {
+            let mut reader = Flaky::new(b"250 too long for the buffer\r\n", seed);
+            let (mut buf, mut filled) = ([0; 8], 0..0);
+            let mut reader = BufReader::new(&mut reader, &mut buf, &mut filled);
+
+            let mut data = Vec::new();
+            loop {
+                match reader.poll_read_until(|&byte| byte == b'\n') {
+                    Ok(block) => {
+                        data.extend_from_slice(block);
+                        break;
+                    }
+                    Err(nb::Error::Other(BufReaderError::FullBuffer(block))) => {
+                        assert_eq!(block.len(), 8);
+                        data.extend_from_slice(block);
+                    }
+                    Err(nb::Error::WouldBlock) => {}
+                    Err(nb::Error::Other(e)) => panic!("{e:?}"),
+                }
+            }
+            assert_eq!(data, b"250 too long for the buffer\r\n", "seed {seed}");
+        }
+    }
+}
@@ -2,7 +2,7 @@ use core::mem::ManuallyDrop;
 
 use embedded_nal::{nb, SocketAddr, TcpClientStack};
 
-use super::{Read, Write};
+use super::{ErrorType, Read, Write};
 
 #[repr(C)]
 pub struct TcpStream<'a, T>
@@ -28,6 +28,22 @@ where
         })
     }
 
+    /// Wrap a socket that is already connected.
+    pub fn from_socket(stack: &'a mut T, socket: T::TcpSocket) -> Self {
+        Self {
+            stack,
+            socket: ManuallyDrop::new(socket),
+        }
+    }
+
+    /// Take the socket out of the stream without closing it.
+    pub fn into_socket(self) -> T::TcpSocket {
+        let mut me = ManuallyDrop::new(self);
+
+        // SAFETY: the socket is never touched again as `me` is never dropped
+        unsafe { ManuallyDrop::take(&mut me.socket) }
+    }
+
     #[inline]
     fn internal_close(&mut self) -> Result<(), T::Error> {
         self.stack.close(
@@ -53,12 +69,17 @@ where
     }
 }
 
-impl<'a, T> Read for TcpStream<'a, T>
+impl<'a, T> ErrorType for TcpStream<'a, T>
 where
     T: TcpClientStack,
 {
     type Error = T::Error;
+}
 
+impl<'a, T> Read for TcpStream<'a, T>
+where
+    T: TcpClientStack,
+{
     fn read(&mut self, buffer: &mut [u8]) -> nb::Result<usize, Self::Error> {
         self.stack.receive(&mut self.socket, buffer)
     }
@@ -68,8 +89,6 @@ impl<'a, T> Write for TcpStream<'a, T>
 where
     T: TcpClientStack,
 {
-    type Error = T::Error;
-
     fn write(&mut self, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
         self.stack.send(&mut self.socket, buffer)
     }
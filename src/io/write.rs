@@ -1,12 +1,18 @@
-use core::fmt::Debug;
+use core::{mem::ManuallyDrop, ops::Range};
 
 use embedded_nal::nb::{self, block};
 
-pub trait Write {
-    type Error: Debug;
+use super::ErrorType;
 
+pub trait Write: ErrorType {
     fn write(&mut self, buffer: &[u8]) -> nb::Result<usize, Self::Error>;
 
+    /// Make sure everything written so far reaches the other end (e.g., for a TLS stream that
+    /// buffers records). Does nothing by default.
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
     // FIXME: Blocking for simplicity
     fn write_all(&mut self, mut buffer: &[u8]) -> Result<(), Self::Error> {
         while !buffer.is_empty() {
@@ -23,6 +29,8 @@ where
 {
     writer: &'a mut W,
     buffer: &'a mut [u8],
+    /// The part of `buffer` already written to `writer`, when partially flushed
+    flushed: usize,
     filled: usize,
 }
 
@@ -34,32 +42,77 @@ where
         Self {
             writer,
             buffer,
+            flushed: 0,
             filled: 0,
         }
     }
 
-    // FIXME: Blocking for now for simplicity
-    pub fn flush(&mut self) -> Result<(), W::Error> {
-        self.writer.write_all(&self.buffer[..self.filled])?;
+    /// Resume a writer released with `into_pending`, with `pending` part of `buffer` still to be
+    /// written.
+    pub(crate) fn with_pending(
+        writer: &'a mut W,
+        buffer: &'a mut [u8],
+        pending: Range<usize>,
+    ) -> Self {
+        Self {
+            writer,
+            buffer,
+            flushed: pending.start,
+            filled: pending.end,
+        }
+    }
+
+    /// Release the writer without flushing, returning the part of the buffer still to be written.
+    pub(crate) fn into_pending(self) -> Range<usize> {
+        let me = ManuallyDrop::new(self);
+        me.flushed..me.filled
+    }
+
+    /// Write the buffered data to the Writer, then flush it.
+    ///
+    /// If the Writer would block, `WouldBlock` is returned and what has been written so far is
+    /// remembered, so the next call carries on from there.
+    pub fn poll_flush(&mut self) -> nb::Result<(), W::Error> {
+        while self.flushed < self.filled {
+            self.flushed += self.writer.write(&self.buffer[self.flushed..self.filled])?;
+        }
+        self.flushed = 0;
         self.filled = 0;
-        Ok(())
+        Ok(self.writer.flush()?)
     }
 
-    fn write_to_buffer(&mut self, data: &[u8]) {
-        self.buffer[self.filled..self.filled + data.len()].copy_from_slice(data);
-        self.filled += data.len();
+    /// Blocking version of [`Self::poll_flush`].
+    pub fn flush(&mut self) -> Result<(), W::Error> {
+        block!(self.poll_flush())
     }
 
-    // FIXME: Blocking for now for simplicity
-    pub fn write(&mut self, data: &[u8]) -> Result<(), W::Error> {
+    /// Buffer as much of `data` as possible, flushing the buffer first if it's full, and return
+    /// how many bytes were taken. Data at least as large as the buffer is written directly.
+    ///
+    /// If the Writer would block, `WouldBlock` is returned and no byte of `data` has been taken.
+    pub fn poll_write(&mut self, data: &[u8]) -> nb::Result<usize, W::Error> {
+        if data.is_empty() {
+            return Ok(0);
+        }
+
         if self.filled + data.len() > self.buffer.len() {
-            self.flush()?;
+            self.poll_flush()?;
         }
 
         if data.len() >= self.buffer.len() {
-            self.writer.write_all(data)?;
+            self.writer.write(data)
         } else {
-            self.write_to_buffer(data);
+            self.buffer[self.filled..self.filled + data.len()].copy_from_slice(data);
+            self.filled += data.len();
+            Ok(data.len())
+        }
+    }
+
+    /// Blocking version of [`Self::poll_write`], writing all of `data`.
+    pub fn write(&mut self, mut data: &[u8]) -> Result<(), W::Error> {
+        while !data.is_empty() {
+            let written = block!(self.poll_write(data))?;
+            data = &data[written..];
         }
         Ok(())
     }
@@ -114,3 +167,73 @@ where
         let _ = self.flush();
     }
 }
+
+/// Write `fmt` through `write_str`, failing with the first error it returned, or with `None` if a
+/// formatting trait implementation failed on its own (e.g., one pulling its data from a source
+/// that failed).
+pub(crate) fn write_fmt_with<E>(
+    fmt: core::fmt::Arguments<'_>,
+    mut write_str: impl FnMut(&str) -> Result<(), E>,
+) -> Result<(), Option<E>> {
+    struct Adapter<F, E> {
+        write_str: F,
+        error: Option<E>,
+    }
+
+    impl<F, E> core::fmt::Write for Adapter<F, E>
+    where
+        F: FnMut(&str) -> Result<(), E>,
+    {
+        fn write_str(&mut self, s: &str) -> core::fmt::Result {
+            (self.write_str)(s).map_err(|e| {
+                self.error = Some(e);
+                core::fmt::Error
+            })
+        }
+    }
+
+    let mut output = Adapter {
+        write_str: &mut write_str,
+        error: None,
+    };
+    core::fmt::write(&mut output, fmt).map_err(|_| output.error)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::io::mock::Flaky;
+
+    #[test]
+    fn poll_write() {
+        let data: Vec<u8> = (0..=255).cycle().take(1000).collect();
+
+        for seed in 1..100 {
+            let mut writer = Flaky::new(b"", seed);
+            let mut buf = [0; 16];
+            let mut buf_writer = BufWriter::new(&mut writer, &mut buf);
+
+            let mut chunks = Flaky::new(b"", seed);
+            let mut remaining = &data[..];
+            let mut would_block = 0;
+            while !remaining.is_empty() {
+                // chunks smaller and larger than the buffer
+                let chunk = &remaining[..remaining.len().min(chunks.next() % 40)];
+                match buf_writer.poll_write(chunk) {
+                    Ok(n) => remaining = &remaining[n..],
+                    Err(nb::Error::WouldBlock) => would_block += 1,
+                }
+            }
+            loop {
+                match buf_writer.poll_flush() {
+                    Ok(()) => break,
+                    Err(nb::Error::WouldBlock) => would_block += 1,
+                }
+            }
+            drop(buf_writer);
+
+            assert_eq!(writer.written, data, "seed {seed}");
+            assert!(would_block > 0);
+        }
+    }
+}
@@ -1,10 +1,11 @@
 #![cfg_attr(not(test), no_std)]
 
 pub mod auth;
+pub mod date;
+pub mod dns;
+pub mod io;
 pub mod message;
 pub mod smtp;
 
-mod io;
-
 #[allow(unused)]
 mod nb_fut;
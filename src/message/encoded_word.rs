@@ -0,0 +1,214 @@
+//! Encoded-words (https://www.rfc-editor.org/rfc/rfc2047) for non-ASCII text in the header
+//! section, which is limited to 7-bit ASCII.
+
+use core::fmt::{self, Write};
+
+use base64::engine::{general_purpose::STANDARD as BASE64, Engine};
+
+/// Maximum length of an encoded-word (https://www.rfc-editor.org/rfc/rfc2047#section-2).
+const MAX_WORD_LEN: usize = 75;
+
+const PREFIX_LEN: usize = "=?UTF-8?B?".len();
+const SUFFIX_LEN: usize = "?=".len();
+
+/// Maximum length of the encoded text of a single encoded-word.
+const MAX_TEXT_LEN: usize = MAX_WORD_LEN - PREFIX_LEN - SUFFIX_LEN;
+
+/// Maximum number of bytes encoded in a single B encoded-word.
+const MAX_B_INPUT: usize = MAX_TEXT_LEN / 4 * 3;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    /// Base64 (https://www.rfc-editor.org/rfc/rfc2047#section-4.1)
+    B,
+    /// Quoted-printable like (https://www.rfc-editor.org/rfc/rfc2047#section-4.2)
+    Q,
+}
+
+/// UTF-8 text displayed as encoded-words, with the B or Q encoding, whichever is shorter, e.g.,
+/// `=?UTF-8?Q?Gr=C3=BC=C3=9Fe?=`.
+///
+/// Encoded-words are split to be at most 75 characters long, without breaking a character, and
+/// separated by a folding white space.
+#[derive(Clone, Copy, Debug)]
+pub struct EncodedWords<'a>(pub &'a str);
+
+/// Length of `byte` with the Q encoding.
+fn q_len(byte: u8) -> usize {
+    if is_q_literal(byte) || byte == b' ' {
+        1
+    } else {
+        3
+    }
+}
+
+/// Whether `byte` can be written as is with the Q encoding, in any header field
+/// (https://www.rfc-editor.org/rfc/rfc2047#section-5).
+fn is_q_literal(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!*+-/".contains(&byte)
+}
+
+impl EncodedWords<'_> {
+    fn encoding(&self) -> Encoding {
+        let q_len: usize = self.0.bytes().map(q_len).sum();
+        let b_len = self.0.len().div_ceil(3) * 4;
+
+        if q_len <= b_len {
+            Encoding::Q
+        } else {
+            Encoding::B
+        }
+    }
+}
+
+/// Split `text` after the characters fitting in a single encoded-word.
+fn split_word(text: &str, encoding: Encoding) -> (&str, &str) {
+    let end = match encoding {
+        Encoding::B => {
+            let mut end = text.len().min(MAX_B_INPUT);
+            while !text.is_char_boundary(end) {
+                end -= 1;
+            }
+            end
+        }
+        Encoding::Q => {
+            let mut len = 0;
+            text.char_indices()
+                .find(|&(i, c)| {
+                    len += text[i..i + c.len_utf8()].bytes().map(q_len).sum::<usize>();
+                    len > MAX_TEXT_LEN
+                })
+                .map_or(text.len(), |(i, _)| i)
+        }
+    };
+
+    text.split_at(end)
+}
+
+fn write_word(f: &mut fmt::Formatter<'_>, word: &str, encoding: Encoding) -> fmt::Result {
+    match encoding {
+        Encoding::B => {
+            let mut buf = [0; MAX_TEXT_LEN];
+            let len = BASE64
+                .encode_slice(word, &mut buf)
+                .expect("word fits an encoded-word");
+            // base64 is ASCII
+            let text = core::str::from_utf8(&buf[..len]).map_err(|_| fmt::Error)?;
+            write!(f, "=?UTF-8?B?{}?=", text)
+        }
+        Encoding::Q => {
+            f.write_str("=?UTF-8?Q?")?;
+            for byte in word.bytes() {
+                match byte {
+                    b' ' => f.write_char('_')?,
+                    byte if is_q_literal(byte) => f.write_char(char::from(byte))?,
+                    byte => write!(f, "={:02X}", byte)?,
+                }
+            }
+            f.write_str("?=")
+        }
+    }
+}
+
+impl fmt::Display for EncodedWords<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let encoding = self.encoding();
+        let mut rest = self.0;
+
+        while !rest.is_empty() {
+            if rest.len() != self.0.len() {
+                f.write_str("\r\n ")?;
+            }
+
+            let (word, tail) = split_word(rest, encoding);
+            write_word(f, word, encoding)?;
+            rest = tail;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Decode encoded-words back, checking each is well formed on its own.
+    fn decode(encoded: &str) -> String {
+        let mut text = String::new();
+        for word in encoded.split("\r\n ") {
+            assert!(word.len() <= MAX_WORD_LEN, "{word:?} too long");
+
+            let (encoding, data) = word
+                .strip_prefix("=?UTF-8?")
+                .and_then(|word| word.strip_suffix("?="))
+                .and_then(|word| word.split_once('?'))
+                .expect("encoded-word");
+            assert!(!data.contains(['?', ' ']));
+
+            let bytes = match encoding {
+                "B" => {
+                    let mut buf = [0; MAX_TEXT_LEN];
+                    let len = BASE64.decode_slice(data, &mut buf).unwrap();
+                    buf[..len].to_vec()
+                }
+                "Q" => {
+                    let mut bytes = Vec::new();
+                    let mut data = data.bytes();
+                    while let Some(byte) = data.next() {
+                        bytes.push(match byte {
+                            b'_' => b' ',
+                            b'=' => {
+                                let hex = [data.next().unwrap(), data.next().unwrap()];
+                                u8::from_str_radix(core::str::from_utf8(&hex).unwrap(), 16).unwrap()
+                            }
+                            byte => byte,
+                        });
+                    }
+                    bytes
+                }
+                _ => panic!("unknown encoding {encoding}"),
+            };
+            text.push_str(core::str::from_utf8(&bytes).expect("whole characters"));
+        }
+        text
+    }
+
+    #[test]
+    fn examples() {
+        assert_eq!(
+            EncodedWords("Café Zürich sensor 3 offline").to_string(),
+            "=?UTF-8?Q?Caf=C3=A9_Z=C3=BCrich_sensor_3_offline?="
+        );
+        assert_eq!(
+            EncodedWords("Grüße aus München").to_string(),
+            "=?UTF-8?B?R3LDvMOfZSBhdXMgTcO8bmNoZW4=?="
+        );
+        assert_eq!(
+            EncodedWords("日本語").to_string(),
+            "=?UTF-8?B?5pel5pys6Kqe?="
+        );
+        // as long as B, preferred as more readable
+        assert_eq!(
+            EncodedWords("Tür 3 = offen? Bitte prüfen, Halle 5").to_string(),
+            "=?UTF-8?Q?T=C3=BCr_3_=3D_offen=3F_Bitte_pr=C3=BCfen=2C_Halle_5?="
+        );
+        assert_eq!(EncodedWords("").to_string(), "");
+    }
+
+    #[test]
+    fn split_long_text() {
+        let texts = [
+            "Temperaturüberschreitung in Halle 3 – Kühlaggregat prüfen, Störung seit 14:02 Uhr",
+            "冷却装置の温度が上限を超えました。第三工場のラインAを確認してください。",
+            "ü日本語ü日本語ü日本語ü日本語ü日本語ü日本語ü日本語ü日本語ü日本語ü日本語",
+            "😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀😀",
+        ];
+
+        for text in texts {
+            let encoded = EncodedWords(text).to_string();
+            assert!(encoded.contains("\r\n "), "{encoded:?} not split");
+            assert_eq!(decode(&encoded), text);
+        }
+    }
+}
@@ -0,0 +1,614 @@
+//! MIME bodies (https://www.rfc-editor.org/rfc/rfc2045), e.g., an HTML text with a plain text
+//! alternative (https://www.rfc-editor.org/rfc/rfc2046#section-5.1.4), or a text with attached
+//! files (https://www.rfc-editor.org/rfc/rfc2183).
+
+use core::{
+    cell::{Cell, RefCell},
+    fmt::{self, Write},
+};
+
+use embedded_nal::nb;
+use heapless::String;
+use rand_core::RngCore;
+
+use super::Body;
+use crate::io::{self, Base64Writer, BufWriter};
+
+/// Maximum length of a boundary (https://www.rfc-editor.org/rfc/rfc2046#section-5.1.1).
+pub const MAX_BOUNDARY_LEN: usize = 70;
+
+/// Boundary delimiting the parts of a `Multipart`, which must not occur in them.
+#[derive(Clone, Copy)]
+pub enum Boundary<'a> {
+    Text(&'a str),
+    /// Generated when the mail is written.
+    Generate(&'a dyn BoundarySource),
+}
+
+impl<'a> From<&'a str> for Boundary<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl<'a, G: BoundarySource> From<&'a G> for Boundary<'a> {
+    fn from(value: &'a G) -> Self {
+        Self::Generate(value)
+    }
+}
+
+impl fmt::Debug for Boundary<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => f.debug_tuple("Text").field(text).finish(),
+            Self::Generate(_) => f.write_str("Generate(..)"),
+        }
+    }
+}
+
+/// A generated boundary.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BoundaryBuf(String<MAX_BOUNDARY_LEN>);
+
+impl BoundaryBuf {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// Source of unique boundaries.
+///
+/// Implemented for an RNG in a `RefCell`, and for a `Cell<u32>` counter, e.g., when the parts
+/// are known not to contain the `=_` of the generated boundaries, as with base64 content.
+pub trait BoundarySource {
+    fn next_boundary(&self) -> BoundaryBuf;
+}
+
+/// Start of the generated boundaries, which can't occur in base64 nor quoted-printable content
+/// (https://www.rfc-editor.org/rfc/rfc2045#section-6.7).
+const BOUNDARY_PREFIX: &str = "=_mailr_";
+
+impl<R: RngCore> BoundarySource for RefCell<R> {
+    fn next_boundary(&self) -> BoundaryBuf {
+        let mut random = [0; 12];
+        self.borrow_mut().fill_bytes(&mut random);
+
+        let mut boundary = String::new();
+        // can't fail, the boundary is 32 characters long
+        let _ = boundary.push_str(BOUNDARY_PREFIX);
+        for byte in random {
+            let _ = write!(boundary, "{:02x}", byte);
+        }
+        BoundaryBuf(boundary)
+    }
+}
+
+impl BoundarySource for Cell<u32> {
+    fn next_boundary(&self) -> BoundaryBuf {
+        let counter = self.get();
+        self.set(counter.wrapping_add(1));
+
+        let mut boundary = String::new();
+        // can't fail, the boundary is 16 characters long
+        let _ = write!(boundary, "{}{:08x}", BOUNDARY_PREFIX, counter);
+        BoundaryBuf(boundary)
+    }
+}
+
+/// A single part with its own content type, e.g., `text/html`.
+#[derive(Clone, Copy, Debug)]
+pub struct Part<'a> {
+    pub content_type: &'a str,
+    pub charset: Option<&'a str>,
+    pub body: Body<'a>,
+}
+
+impl<'a> Part<'a> {
+    pub fn new(content_type: &'a str, body: impl Into<Body<'a>>) -> Self {
+        Self {
+            content_type,
+            charset: None,
+            body: body.into(),
+        }
+    }
+
+    /// A `text/plain` part in UTF-8.
+    pub fn text(body: impl Into<Body<'a>>) -> Self {
+        Self::new("text/plain", body).with_charset("utf-8")
+    }
+
+    /// A `text/html` part in UTF-8.
+    pub fn html(body: impl Into<Body<'a>>) -> Self {
+        Self::new("text/html", body).with_charset("utf-8")
+    }
+
+    pub fn with_charset(mut self, charset: impl Into<Option<&'a str>>) -> Self {
+        self.charset = charset.into();
+        self
+    }
+}
+
+/// Parts delimited by a boundary (https://www.rfc-editor.org/rfc/rfc2046#section-5.1), e.g.,
+/// `multipart/alternative`.
+#[derive(Clone, Copy, Debug)]
+pub struct Multipart<'a> {
+    /// e.g., `alternative` for `multipart/alternative`
+    pub subtype: &'a str,
+    pub parts: &'a [Entity<'a>],
+    pub boundary: Boundary<'a>,
+}
+
+impl<'a> Multipart<'a> {
+    pub fn new(
+        subtype: &'a str,
+        parts: &'a [Entity<'a>],
+        boundary: impl Into<Boundary<'a>>,
+    ) -> Self {
+        Self {
+            subtype,
+            parts,
+            boundary: boundary.into(),
+        }
+    }
+
+    /// The same content in different formats, from the simplest to the richest, e.g., plain text
+    /// then HTML (https://www.rfc-editor.org/rfc/rfc2046#section-5.1.4).
+    pub fn alternative(parts: &'a [Entity<'a>], boundary: impl Into<Boundary<'a>>) -> Self {
+        Self::new("alternative", parts, boundary)
+    }
+
+    /// Independent parts in order, e.g., a text then attachments
+    /// (https://www.rfc-editor.org/rfc/rfc2046#section-5.1.3).
+    pub fn mixed(parts: &'a [Entity<'a>], boundary: impl Into<Boundary<'a>>) -> Self {
+        Self::new("mixed", parts, boundary)
+    }
+}
+
+/// Callback passing the data of an `Attachment` chunk by chunk to the given sink, failing if the
+/// data can't be read. The sink fails once the mail can't be written anymore, and its error should
+/// be returned right away.
+pub type ChunkFn<'a> = dyn Fn(&mut dyn FnMut(&[u8]) -> fmt::Result) -> fmt::Result + 'a;
+
+/// Content of an `Attachment`.
+#[derive(Clone, Copy)]
+pub enum AttachmentData<'a> {
+    Bytes(&'a [u8]),
+    /// Called when the mail is written, passing the data chunk by chunk to the given sink, e.g.,
+    /// while reading a log from flash, so it doesn't need to fit in memory.
+    ///
+    /// If it fails, the send fails with `SendError::MessageFailed` rather than delivering the mail
+    /// with the attachment truncated.
+    Chunks(&'a ChunkFn<'a>),
+}
+
+impl<'a> From<&'a [u8]> for AttachmentData<'a> {
+    fn from(value: &'a [u8]) -> Self {
+        Self::Bytes(value)
+    }
+}
+
+impl<'a, const N: usize> From<&'a [u8; N]> for AttachmentData<'a> {
+    fn from(value: &'a [u8; N]) -> Self {
+        Self::Bytes(value)
+    }
+}
+
+impl<'a> From<&'a ChunkFn<'a>> for AttachmentData<'a> {
+    fn from(value: &'a ChunkFn<'a>) -> Self {
+        Self::Chunks(value)
+    }
+}
+
+impl fmt::Debug for AttachmentData<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bytes(bytes) => write!(f, "Bytes({} bytes)", bytes.len()),
+            Self::Chunks(_) => f.write_str("Chunks(..)"),
+        }
+    }
+}
+
+/// A file attached to a `Multipart::mixed`, base64 encoded as it's written.
+#[derive(Clone, Copy, Debug)]
+pub struct Attachment<'a> {
+    /// Suggested name when saved, possibly non-ASCII
+    /// (https://www.rfc-editor.org/rfc/rfc2231#section-4).
+    pub filename: &'a str,
+    pub content_type: &'a str,
+    pub data: AttachmentData<'a>,
+}
+
+impl<'a> Attachment<'a> {
+    /// e.g., `Attachment::new("log.csv", "text/csv", &log[..])`
+    pub fn new(
+        filename: &'a str,
+        content_type: &'a str,
+        data: impl Into<AttachmentData<'a>>,
+    ) -> Self {
+        Self {
+            filename,
+            content_type,
+            data: data.into(),
+        }
+    }
+}
+
+/// A MIME entity, the body of a `Mail` or a part of a `Multipart`, displayed with its header
+/// fields, e.g., `Content-Type`.
+#[derive(Clone, Copy, Debug)]
+pub enum Entity<'a> {
+    Part(Part<'a>),
+    Multipart(Multipart<'a>),
+    Attachment(Attachment<'a>),
+}
+
+impl<'a> From<Part<'a>> for Entity<'a> {
+    fn from(value: Part<'a>) -> Self {
+        Self::Part(value)
+    }
+}
+
+impl<'a> From<Multipart<'a>> for Entity<'a> {
+    fn from(value: Multipart<'a>) -> Self {
+        Self::Multipart(value)
+    }
+}
+
+impl<'a> From<Attachment<'a>> for Entity<'a> {
+    fn from(value: Attachment<'a>) -> Self {
+        Self::Attachment(value)
+    }
+}
+
+impl fmt::Display for Part<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Content-Type:{}", self.content_type)?;
+        if let Some(charset) = self.charset {
+            write!(f, "; charset={}", charset)?;
+        }
+        write!(f, "\r\n\r\n{}", self.body)
+    }
+}
+
+impl fmt::Display for Multipart<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let generated;
+        let boundary = match self.boundary {
+            Boundary::Text(text) => text,
+            Boundary::Generate(source) => {
+                generated = source.next_boundary();
+                generated.as_str()
+            }
+        };
+
+        // folded before the boundary, which is up to 70 characters long
+        write!(
+            f,
+            "Content-Type:multipart/{};\r\n boundary=\"{}\"\r\n\r\n",
+            self.subtype, boundary
+        )?;
+        for part in self.parts {
+            // the line break before a boundary is part of it, not of the previous part
+            write!(f, "--{}\r\n{}\r\n", boundary, part)?;
+        }
+        write!(f, "--{}--\r\n", boundary)
+    }
+}
+
+impl fmt::Display for Entity<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Part(part) => part.fmt(f),
+            Self::Multipart(multipart) => multipart.fmt(f),
+            Self::Attachment(attachment) => attachment.fmt(f),
+        }
+    }
+}
+
+/// Longest encoded value of a `filename` parameter written on a single line, past which it's
+/// split in sections (https://www.rfc-editor.org/rfc/rfc2231#section-3).
+const FILENAME_SECTION_LEN: usize = 50;
+
+/// Whether `b` can be written as is in an extended parameter value
+/// (https://www.rfc-editor.org/rfc/rfc2231#section-7).
+fn is_attribute_char(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&b)
+}
+
+/// Write the `filename` parameter on a line of its own, quoted if it's short printable ASCII,
+/// otherwise percent encoded in UTF-8 and split in sections of at most `FILENAME_SECTION_LEN`
+/// characters, each on its own line (https://www.rfc-editor.org/rfc/rfc2231#section-4.1).
+fn write_filename(f: &mut fmt::Formatter<'_>, filename: &str) -> fmt::Result {
+    let printable = filename.bytes().all(|b| b.is_ascii_graphic() || b == b' ');
+    let quoted_len = filename.len() + filename.matches(['\\', '"']).count();
+    if printable && quoted_len <= FILENAME_SECTION_LEN {
+        f.write_str(";\r\n filename=\"")?;
+        for c in filename.chars() {
+            if c == '\\' || c == '"' {
+                f.write_char('\\')?;
+            }
+            f.write_char(c)?;
+        }
+        return f.write_char('"');
+    }
+
+    let encoded_len = |b: u8| if is_attribute_char(b) { 1 } else { 3 };
+    let total: usize = filename.bytes().map(encoded_len).sum();
+    let sectioned = total + "utf-8''".len() > FILENAME_SECTION_LEN;
+
+    let mut bytes = filename.bytes().peekable();
+    let mut section = 0;
+    loop {
+        let mut len = if section == 0 {
+            f.write_str(";\r\n filename*")?;
+            if sectioned {
+                f.write_str("0*")?;
+            }
+            f.write_str("=utf-8''")?;
+            "utf-8''".len()
+        } else {
+            write!(f, ";\r\n filename*{}*=", section)?;
+            0
+        };
+
+        while let Some(&b) = bytes.peek() {
+            if len + encoded_len(b) > FILENAME_SECTION_LEN {
+                break;
+            }
+            len += encoded_len(b);
+            bytes.next();
+            if is_attribute_char(b) {
+                f.write_char(char::from(b))?;
+            } else {
+                write!(f, "%{:02X}", b)?;
+            }
+        }
+
+        if bytes.peek().is_none() {
+            return Ok(());
+        }
+        section += 1;
+    }
+}
+
+/// Length of the base64 lines of an attachment (https://www.rfc-editor.org/rfc/rfc2045#section-6.8).
+const BASE64_LINE_LEN: usize = 76;
+
+/// Formatter written to as an `io::Write`, e.g., by a `Base64Writer`. Only ASCII can be written.
+struct FormatterWriter<'f, 'g>(&'f mut fmt::Formatter<'g>);
+
+impl io::ErrorType for FormatterWriter<'_, '_> {
+    type Error = fmt::Error;
+}
+
+impl io::Write for FormatterWriter<'_, '_> {
+    fn write(&mut self, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+        let s = core::str::from_utf8(buffer).map_err(|_| fmt::Error)?;
+        self.0.write_str(s)?;
+        Ok(buffer.len())
+    }
+}
+
+impl fmt::Display for Attachment<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Content-Type:{}\r\n\
+             Content-Transfer-Encoding:base64\r\n\
+             Content-Disposition:attachment",
+            self.content_type
+        )?;
+        write_filename(f, self.filename)?;
+        f.write_str("\r\n\r\n")?;
+
+        // the line break before the closing boundary is part of it
+        let mut f = FormatterWriter(f);
+        let mut buf = [0; BASE64_LINE_LEN + 2];
+        let mut writer = BufWriter::new(&mut f, &mut buf);
+        let mut encoder = Base64Writer::new(&mut writer).with_line_len(BASE64_LINE_LEN);
+        let result = match self.data {
+            AttachmentData::Bytes(bytes) => encoder.write(bytes),
+            AttachmentData::Chunks(func) => func(&mut |chunk| encoder.write(chunk)),
+        };
+        if result.is_err() {
+            // nothing more is written, e.g., the padding, as the rest of the data is missing
+            core::mem::forget(encoder);
+            writer.into_pending();
+            return result;
+        }
+        encoder.finish()?;
+        writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::test::CountingRng;
+
+    #[test]
+    fn alternative() {
+        let parts = [
+            Part::text("Temperature: 21.5 °C").into(),
+            Part::html("<p>Temperature: <b>21.5 °C</b></p>").into(),
+        ];
+        let boundaries = Cell::new(7);
+        let alternative = Multipart::alternative(&parts, &boundaries);
+
+        assert_eq!(
+            alternative.to_string(),
+            "Content-Type:multipart/alternative;\r\n boundary=\"=_mailr_00000007\"\r\n\
+             \r\n\
+             --=_mailr_00000007\r\n\
+             Content-Type:text/plain; charset=utf-8\r\n\
+             \r\n\
+             Temperature: 21.5 °C\r\n\
+             --=_mailr_00000007\r\n\
+             Content-Type:text/html; charset=utf-8\r\n\
+             \r\n\
+             <p>Temperature: <b>21.5 °C</b></p>\r\n\
+             --=_mailr_00000007--\r\n"
+        );
+        assert!(alternative.to_string().contains("=_mailr_00000008"));
+    }
+
+    #[test]
+    fn nested() {
+        let alternative = [
+            Part::text("Hi").into(),
+            Part::new("text/enriched", "<bold>Hi</bold>").into(),
+        ];
+        let parts = [
+            Multipart::alternative(&alternative, "inner").into(),
+            Part::new("text/csv", "t,T\r\n0,21.5\r\n").into(),
+        ];
+        let mixed = Multipart::new("mixed", &parts, "outer");
+
+        assert_eq!(
+            mixed.to_string(),
+            "Content-Type:multipart/mixed;\r\n boundary=\"outer\"\r\n\
+             \r\n\
+             --outer\r\n\
+             Content-Type:multipart/alternative;\r\n boundary=\"inner\"\r\n\
+             \r\n\
+             --inner\r\n\
+             Content-Type:text/plain; charset=utf-8\r\n\
+             \r\n\
+             Hi\r\n\
+             --inner\r\n\
+             Content-Type:text/enriched\r\n\
+             \r\n\
+             <bold>Hi</bold>\r\n\
+             --inner--\r\n\
+             \r\n\
+             --outer\r\n\
+             Content-Type:text/csv\r\n\
+             \r\n\
+             t,T\r\n0,21.5\r\n\
+             \r\n\
+             --outer--\r\n"
+        );
+    }
+
+    #[test]
+    fn attachments() {
+        let parts = [
+            Part::text("Log attached").into(),
+            Attachment::new("log.csv", "text/csv", b"t,T\r\n0,21.5\r\n").into(),
+        ];
+        assert_eq!(
+            Multipart::mixed(&parts, "b").to_string(),
+            "Content-Type:multipart/mixed;\r\n boundary=\"b\"\r\n\
+             \r\n\
+             --b\r\n\
+             Content-Type:text/plain; charset=utf-8\r\n\
+             \r\n\
+             Log attached\r\n\
+             --b\r\n\
+             Content-Type:text/csv\r\n\
+             Content-Transfer-Encoding:base64\r\n\
+             Content-Disposition:attachment;\r\n filename=\"log.csv\"\r\n\
+             \r\n\
+             dCxUDQowLDIxLjUNCg==\r\n\
+             --b--\r\n"
+        );
+
+        let empty = Attachment::new("empty.bin", "application/octet-stream", b"");
+        assert!(empty
+            .to_string()
+            .ends_with("filename=\"empty.bin\"\r\n\r\n"));
+    }
+
+    fn disposition(filename: &str) -> std::string::String {
+        let attachment = Attachment::new(filename, "text/csv", b"").to_string();
+        let start = attachment.find("Content-Disposition:").unwrap();
+        attachment[start..].trim_end().to_string()
+    }
+
+    #[test]
+    fn filenames() {
+        assert_eq!(
+            disposition("log \"March\".csv"),
+            "Content-Disposition:attachment;\r\n filename=\"log \\\"March\\\".csv\""
+        );
+        assert_eq!(
+            disposition("Temperaturverlauf März.csv"),
+            "Content-Disposition:attachment;\r\n \
+             filename*=utf-8''Temperaturverlauf%20M%C3%A4rz.csv"
+        );
+        assert_eq!(
+            disposition("温度ログ_2026-10-18.csv"),
+            "Content-Disposition:attachment;\r\n \
+             filename*0*=utf-8''%E6%B8%A9%E5%BA%A6%E3%83%AD%E3%82%B0_2026-1;\r\n \
+             filename*1*=0-18.csv"
+        );
+        assert_eq!(
+            disposition("line\r\nX-Evil: 1"),
+            "Content-Disposition:attachment;\r\n filename*=utf-8''line%0D%0AX-Evil%3A%201"
+        );
+
+        let long = "x".repeat(120);
+        assert_eq!(
+            disposition(&long),
+            format!(
+                "Content-Disposition:attachment;\r\n \
+                 filename*0*=utf-8''{};\r\n \
+                 filename*1*={};\r\n \
+                 filename*2*={}",
+                &long[..43],
+                &long[..50],
+                &long[..27]
+            )
+        );
+    }
+
+    #[test]
+    fn chunked_attachment() {
+        let data: std::vec::Vec<u8> = (0..=255).cycle().take(500).collect();
+        let bytes = Attachment::new("data.bin", "application/octet-stream", &data[..]).to_string();
+
+        let chunks = |sink: &mut dyn FnMut(&[u8]) -> fmt::Result| data.chunks(7).try_for_each(sink);
+        let chunked = Attachment::new("data.bin", "application/octet-stream", &chunks as &ChunkFn);
+        assert_eq!(chunked.to_string(), bytes);
+
+        // 500 bytes make 667 characters, with padding
+        let (_, content) = bytes.split_once("\r\n\r\n").unwrap();
+        let lines: std::vec::Vec<_> = content.split("\r\n").map(str::len).collect();
+        assert_eq!(lines, [76, 76, 76, 76, 76, 76, 76, 76, 60]);
+    }
+
+    #[test]
+    fn failed_chunks() {
+        // e.g., flash failing halfway through the log
+        let chunks = |sink: &mut dyn FnMut(&[u8]) -> fmt::Result| {
+            sink(&[0; 100])?;
+            Err(fmt::Error)
+        };
+        let attachment =
+            Attachment::new("data.bin", "application/octet-stream", &chunks as &ChunkFn);
+        let mut written = std::string::String::new();
+        assert_eq!(
+            fmt::write(&mut written, format_args!("{attachment}")),
+            Err(fmt::Error)
+        );
+        // nothing was written after the failure, e.g., the padding
+        assert!(!written.ends_with('='));
+    }
+
+    #[test]
+    fn generated_boundaries() {
+        let rng = RefCell::new(CountingRng(0xf8));
+        assert_eq!(
+            rng.next_boundary().as_str(),
+            "=_mailr_f8f9fafbfcfdfeff00010203"
+        );
+        assert_eq!(
+            rng.next_boundary().as_str(),
+            "=_mailr_0405060708090a0b0c0d0e0f"
+        );
+
+        let counter = Cell::new(u32::MAX);
+        assert_eq!(counter.next_boundary().as_str(), "=_mailr_ffffffff");
+        assert_eq!(counter.next_boundary().as_str(), "=_mailr_00000000");
+    }
+}
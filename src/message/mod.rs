@@ -0,0 +1,631 @@
+use core::{
+    cell::{Cell, RefCell},
+    fmt::{self, Write},
+};
+
+use heapless::String;
+use rand_core::RngCore;
+
+use crate::{
+    date::{Clock, DateTime},
+    smtp::ClientId,
+};
+
+mod encoded_word;
+pub use encoded_word::*;
+
+mod validate;
+pub use validate::*;
+
+mod parse;
+pub use parse::*;
+
+mod mime;
+pub use mime::*;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Mailbox<'a> {
+    pub name: Option<&'a str>,
+    pub address: &'a str,
+}
+
+impl<'a> Mailbox<'a> {
+    /// Checked with `validate` when sent.
+    pub fn new(address: &'a str) -> Self {
+        Self {
+            address,
+            name: None,
+        }
+    }
+
+    /// Checked with `validate` when sent.
+    pub fn with_name(name: &'a str, address: &'a str) -> Self {
+        Self {
+            name: Some(name),
+            address,
+        }
+    }
+}
+
+impl<'a> AsRef<Mailbox<'a>> for Mailbox<'a> {
+    fn as_ref(&self) -> &Mailbox<'a> {
+        self
+    }
+}
+
+/// The whole string is taken as the address, see `Mailbox::parse` for `Name <address>` strings.
+impl<'a> From<&'a str> for Mailbox<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::new(value)
+    }
+}
+
+/// Whether `name` can be written as a phrase of atoms separated by single spaces, without
+/// quoting.
+fn is_phrase(name: &str) -> bool {
+    name.split(' ')
+        .all(|word| !word.is_empty() && word.chars().all(is_atext))
+}
+
+/// Displayed as an RFC 5322 mailbox (https://www.rfc-editor.org/rfc/rfc5322#section-3.4), with
+/// the display name as a quoted-string if it isn't a plain phrase, e.g.,
+/// `"Plant 3, Line A" <plant3@example.com>`, or as `EncodedWords` if it isn't ASCII.
+impl fmt::Display for Mailbox<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.name {
+            Some("") | None => {}
+            Some(name) if !name.is_ascii() => write!(f, "{} ", EncodedWords(name))?,
+            Some(name) if is_phrase(name) => write!(f, "{} ", name)?,
+            Some(name) => {
+                f.write_char('"')?;
+                for c in name.chars() {
+                    if matches!(c, '"' | '\\') {
+                        f.write_char('\\')?;
+                    }
+                    f.write_char(c)?;
+                }
+                f.write_str("\" ")?;
+            }
+        }
+        write!(f, "<{}>", self.address)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Envelope<'a, S, I>
+where
+    S: AsRef<str>,
+    I: Iterator<Item = S>,
+{
+    pub sender_addr: Option<&'a str>,
+    pub receiver_addrs: I,
+}
+
+impl<'a, S, I> Envelope<'a, S, I>
+where
+    S: AsRef<str>,
+    I: Iterator<Item = S>,
+{
+    /// The addresses are checked when sent.
+    pub fn new(from: impl Into<Option<&'a str>>, to: impl IntoIterator<IntoIter = I>) -> Self {
+        Self {
+            sender_addr: from.into(),
+            receiver_addrs: to.into_iter(),
+        }
+    }
+}
+
+/// Body of a `Mail`, formatted straight into the message data when sent.
+#[derive(Clone, Copy)]
+pub enum Body<'a> {
+    Text(&'a str),
+    Fmt(fmt::Arguments<'a>),
+    Fn(&'a dyn Fn(&mut fmt::Formatter<'_>) -> fmt::Result),
+    /// MIME parts, e.g., a plain text and an HTML alternative, written with their header fields.
+    Multipart(Multipart<'a>),
+}
+
+impl<'a> From<&'a str> for Body<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl<'a> From<fmt::Arguments<'a>> for Body<'a> {
+    fn from(value: fmt::Arguments<'a>) -> Self {
+        Self::Fmt(value)
+    }
+}
+
+impl fmt::Display for Body<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => f.write_str(text),
+            Self::Fmt(args) => f.write_fmt(*args),
+            Self::Fn(func) => func(f),
+            Self::Multipart(multipart) => multipart.fmt(f),
+        }
+    }
+}
+
+impl fmt::Debug for Body<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => f.debug_tuple("Text").field(text).finish(),
+            Self::Fmt(args) => f.debug_tuple("Fmt").field(args).finish(),
+            Self::Fn(_) => f.write_str("Fn(..)"),
+            Self::Multipart(multipart) => f.debug_tuple("Multipart").field(multipart).finish(),
+        }
+    }
+}
+
+/// Value of the `Date` header of a `Mail`.
+#[derive(Clone, Copy)]
+pub enum Date<'a> {
+    /// Already formatted, e.g., `Fri, 16 Oct 2026 09:12:01 +0200`.
+    Text(&'a str),
+    Time(DateTime),
+    /// Read when the mail is written.
+    Now(&'a dyn Clock),
+}
+
+impl<'a> From<&'a str> for Date<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<DateTime> for Date<'_> {
+    fn from(value: DateTime) -> Self {
+        Self::Time(value)
+    }
+}
+
+impl<'a, C: Clock> From<&'a C> for Date<'a> {
+    fn from(value: &'a C) -> Self {
+        Self::Now(value)
+    }
+}
+
+impl fmt::Display for Date<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => f.write_str(text),
+            Self::Time(time) => time.fmt(f),
+            Self::Now(clock) => clock.now().fmt(f),
+        }
+    }
+}
+
+impl fmt::Debug for Date<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => f.debug_tuple("Text").field(text).finish(),
+            Self::Time(time) => f.debug_tuple("Time").field(time).finish(),
+            Self::Now(_) => f.write_str("Now(..)"),
+        }
+    }
+}
+
+/// Maximum length of a generated Message-ID, without the angle brackets.
+pub const MAX_MESSAGE_ID_LEN: usize = 320;
+
+/// Value of the `Message-ID` header of a `Mail`, without the angle brackets
+/// (https://www.rfc-editor.org/rfc/rfc5322#section-3.6.4).
+#[derive(Clone, Copy)]
+pub enum MessageId<'a> {
+    Text(&'a str),
+    /// Generated when the mail is sent.
+    Generate(&'a dyn MessageIdSource),
+}
+
+impl<'a> From<&'a str> for MessageId<'a> {
+    fn from(value: &'a str) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl<'a, G: MessageIdSource> From<&'a G> for MessageId<'a> {
+    fn from(value: &'a G) -> Self {
+        Self::Generate(value)
+    }
+}
+
+impl fmt::Debug for MessageId<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Text(text) => f.debug_tuple("Text").field(text).finish(),
+            Self::Generate(_) => f.write_str("Generate(..)"),
+        }
+    }
+}
+
+/// A generated Message-ID, without the angle brackets. Displayed with them, e.g.,
+/// `<1792134721.0.9f86d081884c7d65@client.example.com>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MessageIdBuf(String<MAX_MESSAGE_ID_LEN>);
+
+impl MessageIdBuf {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for MessageIdBuf {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "<{}>", self.0)
+    }
+}
+
+/// Source of unique Message-IDs, for mails without an explicit one.
+pub trait MessageIdSource {
+    /// The next Message-ID, or `None` if it doesn't fit in `MessageIdBuf`.
+    fn next_id(&self) -> Option<MessageIdBuf>;
+}
+
+/// Generator of Message-IDs made of a timestamp from `clock`, a counter and random bytes from
+/// `rng`, at the client's domain, e.g., `1792134721.0.9f86d081884c7d65@client.example.com`.
+///
+/// The timestamp keeps IDs unique across restarts, when the counter starts over.
+pub struct MessageIdGenerator<'a, R>
+where
+    R: RngCore,
+{
+    rng: RefCell<R>,
+    counter: Cell<u32>,
+    domain: ClientId<'a>,
+    clock: &'a dyn Clock,
+}
+
+impl<'a, R> MessageIdGenerator<'a, R>
+where
+    R: RngCore,
+{
+    pub fn new(rng: R, domain: ClientId<'a>, clock: &'a dyn Clock) -> Self {
+        Self {
+            rng: RefCell::new(rng),
+            counter: Cell::new(0),
+            domain,
+            clock,
+        }
+    }
+}
+
+impl<R> MessageIdSource for MessageIdGenerator<'_, R>
+where
+    R: RngCore,
+{
+    fn next_id(&self) -> Option<MessageIdBuf> {
+        let mut id = String::new();
+
+        let counter = self.counter.get();
+        self.counter.set(counter.wrapping_add(1));
+        write!(id, "{}.{}.", self.clock.now().timestamp, counter).ok()?;
+
+        let mut random = [0; 8];
+        self.rng.borrow_mut().fill_bytes(&mut random);
+        for byte in random {
+            write!(id, "{:02x}", byte).ok()?;
+        }
+
+        write!(id, "@{}", self.domain).ok()?;
+        Some(MessageIdBuf(id))
+    }
+}
+
+/// Header fields of a `Mail`, other than the addresses and the subject
+/// (https://www.rfc-editor.org/rfc/rfc5322#section-3.6).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Headers<'a> {
+    /// Date and time the mail is sent (https://www.rfc-editor.org/rfc/rfc5322#section-3.3).
+    pub date: Option<Date<'a>>,
+    /// Unique identifier of the mail.
+    pub message_id: Option<MessageId<'a>>,
+    /// Mailbox of the agent sending the mail, if not the author in `from`.
+    pub sender: Option<Mailbox<'a>>,
+    pub reply_to: Option<Mailbox<'a>>,
+    /// Message-ID of the mail replied to, without the angle brackets.
+    pub in_reply_to: Option<&'a str>,
+    /// Message-IDs of the mails in the thread, without the angle brackets.
+    pub references: &'a [&'a str],
+    /// Other header fields, as (name, value) pairs.
+    pub custom: &'a [(&'a str, &'a str)],
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Mail<'a, Mb, To, Cc, Bcc>
+where
+    Mb: AsRef<Mailbox<'a>>,
+    To: Iterator<Item = Mb>,
+    Cc: Iterator<Item = Mb>,
+    Bcc: Iterator<Item = Mb>,
+{
+    pub from: Option<Mailbox<'a>>,
+    pub to: To,
+    pub cc: Cc,
+    pub bcc: Bcc,
+    pub subject: Option<&'a str>,
+    pub headers: Headers<'a>,
+    pub body: Option<Body<'a>>,
+}
+
+type NoMailboxIter<'a> = core::option::Iter<'a, Mailbox<'a>>;
+
+impl<'a> Mail<'a, &'a Mailbox<'a>, NoMailboxIter<'a>, NoMailboxIter<'a>, NoMailboxIter<'a>> {
+    pub fn new() -> Self {
+        Self {
+            from: None,
+            to: None.iter(),
+            cc: None.iter(),
+            bcc: None.iter(),
+            subject: None,
+            headers: Headers::default(),
+            body: None,
+        }
+    }
+}
+
+impl<'a> Default
+    for Mail<'a, &'a Mailbox<'a>, NoMailboxIter<'a>, NoMailboxIter<'a>, NoMailboxIter<'a>>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a, Mb, To, Cc, Bcc> Mail<'a, Mb, To, Cc, Bcc>
+where
+    Mb: AsRef<Mailbox<'a>>,
+    To: Iterator<Item = Mb>,
+    Cc: Iterator<Item = Mb>,
+    Bcc: Iterator<Item = Mb>,
+{
+    pub fn from(mut self, value: impl Into<Mailbox<'a>>) -> Self {
+        self.from = Some(value.into());
+        self
+    }
+
+    pub fn replace_to<I>(
+        self,
+        value: impl IntoIterator<IntoIter = I>,
+    ) -> (Mail<'a, Mb, I, Cc, Bcc>, To)
+    where
+        I: Iterator<Item = Mb>,
+    {
+        let mail = Mail {
+            from: self.from,
+            to: value.into_iter(),
+            cc: self.cc,
+            bcc: self.bcc,
+            subject: self.subject,
+            headers: self.headers,
+            body: self.body,
+        };
+
+        (mail, self.to)
+    }
+
+    pub fn to<I>(self, value: impl IntoIterator<IntoIter = I>) -> Mail<'a, Mb, I, Cc, Bcc>
+    where
+        I: Iterator<Item = Mb>,
+    {
+        self.replace_to(value).0
+    }
+
+    pub fn replace_cc<I>(
+        self,
+        value: impl IntoIterator<IntoIter = I>,
+    ) -> (Mail<'a, Mb, To, I, Bcc>, Cc)
+    where
+        I: Iterator<Item = Mb>,
+    {
+        let mail = Mail {
+            from: self.from,
+            to: self.to,
+            cc: value.into_iter(),
+            bcc: self.bcc,
+            subject: self.subject,
+            headers: self.headers,
+            body: self.body,
+        };
+
+        (mail, self.cc)
+    }
+
+    pub fn cc<I>(self, value: impl IntoIterator<IntoIter = I>) -> Mail<'a, Mb, To, I, Bcc>
+    where
+        I: Iterator<Item = Mb>,
+    {
+        self.replace_cc(value).0
+    }
+
+    pub fn replace_bcc<I>(
+        self,
+        value: impl IntoIterator<IntoIter = I>,
+    ) -> (Mail<'a, Mb, To, Cc, I>, Bcc)
+    where
+        I: Iterator<Item = Mb>,
+    {
+        let mail = Mail {
+            from: self.from,
+            to: self.to,
+            cc: self.cc,
+            bcc: value.into_iter(),
+            subject: self.subject,
+            headers: self.headers,
+            body: self.body,
+        };
+
+        (mail, self.bcc)
+    }
+
+    pub fn bcc<I>(self, value: impl IntoIterator<IntoIter = I>) -> Mail<'a, Mb, To, Cc, I>
+    where
+        I: Iterator<Item = Mb>,
+    {
+        self.replace_bcc(value).0
+    }
+
+    pub fn subject(mut self, value: impl Into<Option<&'a str>>) -> Self {
+        self.subject = value.into();
+        self
+    }
+
+    /// Date of the mail, e.g., a `DateTime`, or a `Clock` to read it from when sent.
+    pub fn date(mut self, value: impl Into<Date<'a>>) -> Self {
+        self.headers.date = Some(value.into());
+        self
+    }
+
+    /// Message-ID of the mail, or a `MessageIdSource` (e.g., a `MessageIdGenerator`) to generate
+    /// it from when sent.
+    pub fn message_id(mut self, value: impl Into<MessageId<'a>>) -> Self {
+        self.headers.message_id = Some(value.into());
+        self
+    }
+
+    pub fn sender(mut self, value: impl Into<Mailbox<'a>>) -> Self {
+        self.headers.sender = Some(value.into());
+        self
+    }
+
+    pub fn reply_to(mut self, value: impl Into<Mailbox<'a>>) -> Self {
+        self.headers.reply_to = Some(value.into());
+        self
+    }
+
+    pub fn in_reply_to(mut self, value: impl Into<Option<&'a str>>) -> Self {
+        self.headers.in_reply_to = value.into();
+        self
+    }
+
+    pub fn references(mut self, value: &'a [&'a str]) -> Self {
+        self.headers.references = value;
+        self
+    }
+
+    /// Other header fields, as (name, value) pairs, e.g., `&[("X-Priority", "1")]`.
+    pub fn custom_headers(mut self, value: &'a [(&'a str, &'a str)]) -> Self {
+        self.headers.custom = value;
+        self
+    }
+
+    pub fn body(mut self, value: impl Into<Option<&'a str>>) -> Self {
+        self.body = value.into().map(Body::Text);
+        self
+    }
+
+    /// Body formatted when sent, e.g., `body_fmt(format_args!("T={}", t))`, without the need of a
+    /// buffer for the formatted text.
+    pub fn body_fmt(mut self, value: fmt::Arguments<'a>) -> Self {
+        self.body = Some(Body::Fmt(value));
+        self
+    }
+
+    /// Body written by `func` when sent, e.g., `body_with(&|f| write!(f, "T={}", t))`.
+    pub fn body_with(mut self, func: &'a dyn Fn(&mut fmt::Formatter<'_>) -> fmt::Result) -> Self {
+        self.body = Some(Body::Fn(func));
+        self
+    }
+
+    /// MIME body (https://www.rfc-editor.org/rfc/rfc2045), e.g.,
+    /// `multipart(Multipart::alternative(&[Part::text(t).into(), Part::html(h).into()], &rng))`.
+    pub fn multipart(mut self, value: Multipart<'a>) -> Self {
+        self.body = Some(Body::Multipart(value));
+        self
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+
+    /// Not random at all, for predictable IDs.
+    pub(crate) struct CountingRng(pub u8);
+
+    impl RngCore for CountingRng {
+        fn next_u32(&mut self) -> u32 {
+            rand_core::impls::next_u32_via_fill(self)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            rand_core::impls::next_u64_via_fill(self)
+        }
+
+        fn fill_bytes(&mut self, dst: &mut [u8]) {
+            for byte in dst {
+                *byte = self.0;
+                self.0 = self.0.wrapping_add(1);
+            }
+        }
+    }
+
+    fn display(name: &str) -> std::string::String {
+        Mailbox::with_name(name, "plant3@example.com").to_string()
+    }
+
+    #[test]
+    fn mailbox_display_name() {
+        assert_eq!(
+            Mailbox::new("plant3@example.com").to_string(),
+            "<plant3@example.com>"
+        );
+        assert_eq!(display(""), "<plant3@example.com>");
+        assert_eq!(display("Plant3"), "Plant3 <plant3@example.com>");
+        assert_eq!(
+            display("Plant 3 Line A"),
+            "Plant 3 Line A <plant3@example.com>"
+        );
+        assert_eq!(
+            display("Usine Montréal"),
+            "=?UTF-8?Q?Usine_Montr=C3=A9al?= <plant3@example.com>"
+        );
+        assert_eq!(
+            display("Plant 3, Line A"),
+            "\"Plant 3, Line A\" <plant3@example.com>"
+        );
+        assert_eq!(display("J. Smith"), "\"J. Smith\" <plant3@example.com>");
+        assert_eq!(display(" Plant3"), "\" Plant3\" <plant3@example.com>");
+        assert_eq!(display("Plant  3"), "\"Plant  3\" <plant3@example.com>");
+        assert_eq!(
+            display(r#"Plant "3" \ A"#),
+            r#""Plant \"3\" \\ A" <plant3@example.com>"#
+        );
+    }
+
+    #[test]
+    fn generated_message_ids() {
+        /// Ticking a second every time it's read.
+        struct Rtc(Cell<i64>);
+
+        impl Clock for Rtc {
+            fn now(&self) -> DateTime {
+                let now = self.0.get();
+                self.0.set(now + 1);
+                DateTime::new(now, 120)
+            }
+        }
+
+        let rtc = Rtc(Cell::new(1_792_134_721));
+        let generator =
+            MessageIdGenerator::new(CountingRng(0), ClientId::new("client.example.com"), &rtc);
+
+        let ids: [_; 3] = core::array::from_fn(|_| generator.next_id().expect("generated"));
+        assert_eq!(
+            ids.each_ref().map(MessageIdBuf::as_str),
+            [
+                "1792134721.0.0001020304050607@client.example.com",
+                "1792134722.1.08090a0b0c0d0e0f@client.example.com",
+                "1792134723.2.1011121314151617@client.example.com",
+            ]
+        );
+
+        // after a restart, the timestamp keeps the IDs unique
+        let generator =
+            MessageIdGenerator::new(CountingRng(0), ClientId::new("client.example.com"), &rtc);
+        assert_eq!(
+            generator.next_id().expect("generated").as_str(),
+            "1792134724.0.0001020304050607@client.example.com"
+        );
+    }
+}
@@ -0,0 +1,527 @@
+//! Parsing of mailboxes and address lists (https://www.rfc-editor.org/rfc/rfc5322#section-3.4),
+//! e.g., `"Ops Team" <ops@example.com>, Plant 3: a@example.com, b@example.com;`, into `Mailbox`
+//! values borrowing from the input.
+
+use super::{is_atext, Mailbox};
+
+/// Error parsing a mailbox or an address list, at byte `position` of the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    pub position: usize,
+    pub kind: ParseErrorKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ParseErrorKind {
+    /// The input ended early, e.g., in a quoted string or before the closing `>`.
+    UnexpectedEnd,
+    /// A character not allowed there, e.g., a space in an address.
+    UnexpectedChar,
+}
+
+/// Whether `byte` can be part of an atom, with UTF-8 allowed
+/// (https://www.rfc-editor.org/rfc/rfc6532#section-3.2).
+fn is_atext_byte(byte: u8) -> bool {
+    !byte.is_ascii() || is_atext(char::from(byte))
+}
+
+struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(input: &'a str) -> Self {
+        Self { input, pos: 0 }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.as_bytes().get(self.pos).copied()
+    }
+
+    /// Error at the current position, for what's there.
+    fn unexpected(&self) -> ParseError {
+        ParseError {
+            position: self.pos,
+            kind: match self.peek() {
+                Some(_) => ParseErrorKind::UnexpectedChar,
+                None => ParseErrorKind::UnexpectedEnd,
+            },
+        }
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), ParseError> {
+        if self.peek() == Some(byte) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.unexpected())
+        }
+    }
+
+    /// Skip white space and comments, returning the content of the last comment, if any.
+    fn skip_cfws(&mut self) -> Result<Option<&'a str>, ParseError> {
+        let mut comment = None;
+        loop {
+            match self.peek() {
+                Some(b' ' | b'\t') => self.pos += 1,
+                Some(b'(') => comment = Some(self.comment()?),
+                _ => return Ok(comment),
+            }
+        }
+    }
+
+    /// Skip a quoted-pair (https://www.rfc-editor.org/rfc/rfc5322#section-3.2.1), after its `\`.
+    fn quoted_pair(&mut self) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(b'\r' | b'\n') | None => Err(self.unexpected()),
+            Some(_) => {
+                self.pos += 1;
+                Ok(())
+            }
+        }
+    }
+
+    /// Skip a comment, possibly nested, returning its content.
+    fn comment(&mut self) -> Result<&'a str, ParseError> {
+        self.expect(b'(')?;
+        let start = self.pos;
+        let mut depth = 1;
+
+        loop {
+            match self.peek() {
+                Some(b'(') => depth += 1,
+                Some(b')') if depth == 1 => break,
+                Some(b')') => depth -= 1,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    self.quoted_pair()?;
+                    continue;
+                }
+                Some(b'\r' | b'\n') | None => return Err(self.unexpected()),
+                Some(_) => {}
+            }
+            self.pos += 1;
+        }
+
+        let content = &self.input[start..self.pos];
+        self.pos += 1;
+        Ok(content.trim())
+    }
+
+    /// Skip a quoted-string, including its quotes.
+    fn quoted_string(&mut self) -> Result<(), ParseError> {
+        self.expect(b'"')?;
+        loop {
+            match self.peek() {
+                Some(b'"') => break,
+                Some(b'\\') => {
+                    self.pos += 1;
+                    self.quoted_pair()?;
+                    continue;
+                }
+                Some(b'\r' | b'\n') | None => return Err(self.unexpected()),
+                Some(_) => {}
+            }
+            self.pos += 1;
+        }
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Skip an atom, failing if there is none.
+    fn atom(&mut self) -> Result<(), ParseError> {
+        let start = self.pos;
+        while self.peek().is_some_and(is_atext_byte) {
+            self.pos += 1;
+        }
+
+        if self.pos == start {
+            Err(self.unexpected())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn dot_atom(&mut self) -> Result<(), ParseError> {
+        self.atom()?;
+        while self.peek() == Some(b'.') {
+            self.pos += 1;
+            self.atom()?;
+        }
+        Ok(())
+    }
+
+    /// Skip a domain-literal, e.g., `[192.0.2.1]`.
+    fn domain_literal(&mut self) -> Result<(), ParseError> {
+        self.expect(b'[')?;
+        while self
+            .peek()
+            .is_some_and(|b| b.is_ascii_graphic() && !b"[]\\".contains(&b))
+        {
+            self.pos += 1;
+        }
+        self.expect(b']')
+    }
+
+    /// Parse an addr-spec, e.g., `ops@example.com`, without white space nor comments in it.
+    fn addr_spec(&mut self) -> Result<&'a str, ParseError> {
+        let start = self.pos;
+
+        if self.peek() == Some(b'"') {
+            self.quoted_string()?;
+        } else {
+            self.dot_atom()?;
+        }
+        self.expect(b'@')?;
+        if self.peek() == Some(b'[') {
+            self.domain_literal()?;
+        } else {
+            self.dot_atom()?;
+        }
+
+        Ok(&self.input[start..self.pos])
+    }
+
+    /// Skip the words of a display name or group name, returning it.
+    ///
+    /// The name is borrowed as written, without the quotes if it is a single quoted-string.
+    /// Periods are allowed in words, as in the obsolete syntax (e.g., `John Q. Public`).
+    fn phrase(&mut self) -> Result<Option<&'a str>, ParseError> {
+        let mut span = None::<(usize, usize)>;
+        let mut words = 0;
+
+        loop {
+            self.skip_cfws()?;
+            let start = self.pos;
+            match self.peek() {
+                Some(b'"') => self.quoted_string()?,
+                Some(b) if is_atext_byte(b) || b == b'.' => {
+                    while self.peek().is_some_and(|b| is_atext_byte(b) || b == b'.') {
+                        self.pos += 1;
+                    }
+                }
+                _ => break,
+            }
+            span = Some((span.map_or(start, |(start, _)| start), self.pos));
+            words += 1;
+        }
+
+        Ok(span.map(|(start, end)| {
+            let phrase = &self.input[start..end];
+            match phrase.strip_prefix('"').and_then(|p| p.strip_suffix('"')) {
+                Some(content) if words == 1 => content,
+                _ => phrase,
+            }
+        }))
+    }
+
+    /// Parse a mailbox, either `Name <addr-spec>` or a bare `addr-spec`, taking the name from a
+    /// trailing comment in the latter case, as in `ops@example.com (Ops Team)`.
+    fn mailbox(&mut self) -> Result<Mailbox<'a>, ParseError> {
+        let start = self.pos;
+        let name = self.phrase()?;
+
+        if self.peek() == Some(b'<') {
+            self.pos += 1;
+            let address = self.addr_spec()?;
+            self.expect(b'>')?;
+            self.skip_cfws()?;
+            return Ok(Mailbox { name, address });
+        }
+
+        // not a display name, but the local part of the address
+        self.pos = start;
+        self.skip_cfws()?;
+        let address = self.addr_spec()?;
+        let comment = self.skip_cfws()?;
+
+        Ok(Mailbox {
+            name: comment.filter(|comment| !comment.is_empty()),
+            address,
+        })
+    }
+}
+
+impl<'a> Mailbox<'a> {
+    /// Parse a single mailbox, e.g., `"Ops Team" <ops@example.com>` or `ops@example.com`.
+    ///
+    /// Quoted-pairs (e.g., `\"`) are kept in the name as written, as it borrows from the input.
+    pub fn parse(input: &'a str) -> Result<Self, ParseError> {
+        let mut parser = Parser::new(input);
+        let mailbox = parser.mailbox()?;
+
+        match parser.peek() {
+            Some(_) => Err(parser.unexpected()),
+            None => Ok(mailbox),
+        }
+    }
+}
+
+/// Iterator over the mailboxes of a comma separated address list, including the members of the
+/// groups in it (https://www.rfc-editor.org/rfc/rfc5322#section-3.4).
+///
+/// Ends after the first error.
+pub struct AddressList<'a> {
+    parser: Parser<'a>,
+    in_group: bool,
+    done: bool,
+}
+
+impl<'a> AddressList<'a> {
+    /// Parse `input` lazily, e.g., `Ops <ops@example.com>, Plant 3: a@example.com;`.
+    pub fn parse(input: &'a str) -> Self {
+        Self {
+            parser: Parser::new(input),
+            in_group: false,
+            done: false,
+        }
+    }
+
+    fn next_mailbox(&mut self) -> Result<Option<Mailbox<'a>>, ParseError> {
+        let parser = &mut self.parser;
+
+        loop {
+            parser.skip_cfws()?;
+            match parser.peek() {
+                None if self.in_group => return Err(parser.unexpected()),
+                None => return Ok(None),
+                // empty list element, as allowed by the obsolete syntax
+                Some(b',') => {
+                    parser.pos += 1;
+                    continue;
+                }
+                Some(b';') if self.in_group => {
+                    parser.pos += 1;
+                    self.in_group = false;
+                    parser.skip_cfws()?;
+                    match parser.peek() {
+                        Some(b',') => parser.pos += 1,
+                        None => {}
+                        Some(_) => return Err(parser.unexpected()),
+                    }
+                    continue;
+                }
+                Some(_) => {}
+            }
+
+            let start = parser.pos;
+            if !self.in_group {
+                parser.phrase()?;
+                if parser.peek() == Some(b':') {
+                    parser.pos += 1;
+                    self.in_group = true;
+                    continue;
+                }
+                parser.pos = start;
+            }
+
+            let mailbox = parser.mailbox()?;
+            match parser.peek() {
+                Some(b',') => parser.pos += 1,
+                Some(b';') if self.in_group => {}
+                None => {}
+                Some(_) => return Err(parser.unexpected()),
+            }
+            return Ok(Some(mailbox));
+        }
+    }
+}
+
+impl<'a> Iterator for AddressList<'a> {
+    type Item = Result<Mailbox<'a>, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let next = self.next_mailbox().transpose();
+        self.done = !matches!(next, Some(Ok(_)));
+        next
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn parse(input: &str) -> Result<(Option<&str>, &str), ParseError> {
+        Mailbox::parse(input).map(|mb| (mb.name, mb.address))
+    }
+
+    fn parse_list(input: &str) -> Result<Vec<(Option<&str>, &str)>, ParseError> {
+        AddressList::parse(input)
+            .map(|mb| mb.map(|mb| (mb.name, mb.address)))
+            .collect()
+    }
+
+    fn error(position: usize, kind: ParseErrorKind) -> ParseError {
+        ParseError { position, kind }
+    }
+
+    #[test]
+    fn mailboxes() {
+        assert_eq!(parse("ops@example.com"), Ok((None, "ops@example.com")));
+        assert_eq!(parse("  ops@example.com "), Ok((None, "ops@example.com")));
+        assert_eq!(parse("<ops@example.com>"), Ok((None, "ops@example.com")));
+        assert_eq!(
+            parse("\"Ops Team\" <ops@example.com>"),
+            Ok((Some("Ops Team"), "ops@example.com"))
+        );
+        assert_eq!(
+            parse("Ops Team <ops@example.com>"),
+            Ok((Some("Ops Team"), "ops@example.com"))
+        );
+        assert_eq!(
+            parse("John Q. Public <john@example.com>"),
+            Ok((Some("John Q. Public"), "john@example.com"))
+        );
+        assert_eq!(
+            parse("\"Plant 3, Line A\" <plant3@example.com>"),
+            Ok((Some("Plant 3, Line A"), "plant3@example.com"))
+        );
+        assert_eq!(
+            parse("Jürgen <juergen@example.com>"),
+            Ok((Some("Jürgen"), "juergen@example.com"))
+        );
+        assert_eq!(
+            parse("(pager) Ops (team) <ops@example.com> (24/7)"),
+            Ok((Some("Ops"), "ops@example.com"))
+        );
+        assert_eq!(
+            parse("ops@example.com (Ops (on call) Team)"),
+            Ok((Some("Ops (on call) Team"), "ops@example.com"))
+        );
+        assert_eq!(
+            parse("\"ops team\"@example.com"),
+            Ok((None, "\"ops team\"@example.com"))
+        );
+        assert_eq!(
+            parse("Ops <ops@[192.0.2.1]>"),
+            Ok((Some("Ops"), "ops@[192.0.2.1]"))
+        );
+        assert_eq!(
+            parse(r#""Ops \"A\"" <ops@example.com>"#),
+            Ok((Some(r#"Ops \"A\""#), "ops@example.com"))
+        );
+    }
+
+    #[test]
+    fn malformed_mailboxes() {
+        use ParseErrorKind::*;
+
+        assert_eq!(parse(""), Err(error(0, UnexpectedEnd)));
+        assert_eq!(parse("ops"), Err(error(3, UnexpectedEnd)));
+        assert_eq!(
+            parse("Ops Team ops@example.com"),
+            Err(error(3, UnexpectedChar))
+        );
+        assert_eq!(parse("Ops <ops@example.com"), Err(error(20, UnexpectedEnd)));
+        assert_eq!(
+            parse("Ops <ops @example.com>"),
+            Err(error(8, UnexpectedChar))
+        );
+        assert_eq!(
+            parse("\"Ops <ops@example.com>"),
+            Err(error(22, UnexpectedEnd))
+        );
+        assert_eq!(parse("ops@example.com (Ops"), Err(error(20, UnexpectedEnd)));
+        assert_eq!(parse("ops@example..com"), Err(error(12, UnexpectedChar)));
+        assert_eq!(
+            parse("ops@example.com, b@example.com"),
+            Err(error(15, UnexpectedChar))
+        );
+        assert_eq!(
+            parse("Ops\r\nBcc: <ops@example.com>"),
+            Err(error(3, UnexpectedChar))
+        );
+    }
+
+    #[test]
+    fn address_lists() {
+        assert_eq!(parse_list(""), Ok(vec![]));
+        assert_eq!(
+            parse_list("\"Ops Team\" <ops@example.com>, plant3@example.com (Plant 3)"),
+            Ok(vec![
+                (Some("Ops Team"), "ops@example.com"),
+                (Some("Plant 3"), "plant3@example.com"),
+            ])
+        );
+        assert_eq!(
+            parse_list(
+                "Plant 3: Line A <a@example.com>, b@example.com;, ops@example.com, \
+                 undisclosed-recipients:;"
+            ),
+            Ok(vec![
+                (Some("Line A"), "a@example.com"),
+                (None, "b@example.com"),
+                (None, "ops@example.com"),
+            ])
+        );
+        assert_eq!(
+            parse_list("a@example.com,, ,b@example.com,"),
+            Ok(vec![(None, "a@example.com"), (None, "b@example.com")])
+        );
+    }
+
+    #[test]
+    fn malformed_address_lists() {
+        use ParseErrorKind::*;
+
+        assert_eq!(
+            parse_list("a@example.com, b@example.com c@example.com"),
+            Err(error(29, UnexpectedChar))
+        );
+        assert_eq!(
+            parse_list("a@example.com; b@example.com"),
+            Err(error(13, UnexpectedChar))
+        );
+        assert_eq!(
+            parse_list("Plant 3: a@example.com"),
+            Err(error(22, UnexpectedEnd))
+        );
+        assert_eq!(
+            parse_list("Plant 3: a@example.com; b@example.com"),
+            Err(error(24, UnexpectedChar))
+        );
+
+        let mut list = AddressList::parse("a@example.com, (b@example.com");
+        assert!(matches!(list.next(), Some(Ok(_))));
+        assert_eq!(list.next().unwrap().err(), Some(error(29, UnexpectedEnd)));
+        assert!(list.next().is_none());
+    }
+
+    fn display_name() -> impl Strategy<Value = String> {
+        let char = prop_oneof![
+            4 => prop::char::range('a', 'z'),
+            1 => prop::sample::select(&[' ', ',', '.', ':', ';', '<', '>', '@', '(', ')', '\'', 'é'][..]),
+        ];
+        prop::collection::vec(char, 0..30).prop_map(|chars| chars.into_iter().collect())
+    }
+
+    proptest! {
+        #[test]
+        fn display_round_trip(
+            name in display_name(),
+            local in "[a-z0-9+]{1,5}(\\.[a-z0-9+]{1,5}){0,2}",
+        ) {
+            let address = format!("{local}@example.com");
+            let mailbox = Mailbox::with_name(&name, &address).to_string();
+
+            // non-ASCII names are displayed as encoded-words, parsed back as is
+            let parsed = Mailbox::parse(&mailbox).unwrap();
+            if name.is_ascii() {
+                let expected = Some(name.as_str()).filter(|name| !name.is_empty());
+                prop_assert_eq!(parsed.name, expected);
+            }
+            prop_assert_eq!(parsed.address, address.as_str());
+
+            let list = format!("{mailbox}, Group: {mailbox}, {mailbox};");
+            let parsed: Vec<_> = AddressList::parse(&list).collect::<Result<_, _>>().unwrap();
+            prop_assert_eq!(parsed.len(), 3);
+        }
+    }
+}
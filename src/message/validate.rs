@@ -0,0 +1,409 @@
+//! Checks of the input written to the message or the SMTP session, so it can't inject header
+//! fields or commands.
+
+use core::{
+    net::{Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+};
+
+use super::{Body, Boundary, Date, Entity, Envelope, Headers, Mail, Mailbox, MessageId, Multipart};
+
+/// Input rejected before being sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputError {
+    /// A header field value (e.g., the subject or a display name) contains CR, LF or NUL, which
+    /// could inject other header fields or SMTP commands.
+    HeaderValue,
+    /// A custom header field name isn't printable ASCII without colon
+    /// (https://www.rfc-editor.org/rfc/rfc5322#section-3.6.8).
+    HeaderName,
+    /// An address isn't a `local-part@domain` mailbox
+    /// (https://www.rfc-editor.org/rfc/rfc5321#section-4.1.2).
+    Address,
+    /// A client ID isn't a domain nor an address literal
+    /// (https://www.rfc-editor.org/rfc/rfc5321#section-4.1.1.1).
+    ClientId,
+    /// A MIME boundary isn't 1 to 70 allowed characters, not ending with a space
+    /// (https://www.rfc-editor.org/rfc/rfc2046#section-5.1.1).
+    Boundary,
+    /// A date isn't within the years 0 to 9999, or its offset from UTC isn't less than a day
+    /// (see `DateTime::validate`).
+    Date,
+}
+
+/// Whether `c` can be part of an atom (https://www.rfc-editor.org/rfc/rfc5322#section-3.2.3).
+pub(crate) fn is_atext(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~".contains(c)
+}
+
+pub(crate) fn check_header_value(value: &str) -> Result<(), InputError> {
+    if value.contains(['\r', '\n', '\0']) {
+        Err(InputError::HeaderValue)
+    } else {
+        Ok(())
+    }
+}
+
+fn check_header_name(name: &str) -> Result<(), InputError> {
+    if !name.is_empty() && name.bytes().all(|b| b.is_ascii_graphic() && b != b':') {
+        Ok(())
+    } else {
+        Err(InputError::HeaderName)
+    }
+}
+
+/// Whether `s` is a dot-atom, e.g., `john.smith`.
+fn is_dot_atom(s: &str) -> bool {
+    s.split('.')
+        .all(|atom| !atom.is_empty() && atom.chars().all(is_atext))
+}
+
+/// Whether `s` is a quoted-string without folding white space, e.g., `"john smith"`.
+fn is_quoted_string(s: &str) -> bool {
+    let Some(content) = s.strip_prefix('"').and_then(|s| s.strip_suffix('"')) else {
+        return false;
+    };
+
+    let mut bytes = content.bytes();
+    while let Some(byte) = bytes.next() {
+        let byte = match byte {
+            b'\\' => match bytes.next() {
+                Some(escaped) => escaped,
+                None => return false,
+            },
+            b'"' => return false,
+            byte => byte,
+        };
+        if !(byte.is_ascii_graphic() || byte == b' ' || byte == b'\t') {
+            return false;
+        }
+    }
+    true
+}
+
+/// Whether `s` is a domain of dot separated labels made of letters, digits and hyphens
+/// (https://www.rfc-editor.org/rfc/rfc5321#section-4.1.2).
+fn is_domain(s: &str) -> bool {
+    s.split('.').all(|label| {
+        !label.is_empty()
+            && !label.ends_with('-')
+            && label.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+/// Whether `s` is an address literal, e.g., `[192.0.2.1]` or `[IPv6:2001:db8::1]`
+/// (https://www.rfc-editor.org/rfc/rfc5321#section-4.1.3).
+fn is_address_literal(s: &str) -> bool {
+    let Some(content) = s.strip_prefix('[').and_then(|s| s.strip_suffix(']')) else {
+        return false;
+    };
+
+    if let Some(addr) = content.strip_prefix("IPv6:") {
+        return Ipv6Addr::from_str(addr).is_ok();
+    }
+
+    match content.split_once(':') {
+        // General-address-literal
+        Some((tag, content)) => {
+            is_domain(tag)
+                && !content.is_empty()
+                && content
+                    .bytes()
+                    .all(|b| b.is_ascii_graphic() && !b"[]\\".contains(&b))
+        }
+        None => Ipv4Addr::from_str(content).is_ok(),
+    }
+}
+
+pub(crate) fn check_client_id(id: &str) -> Result<(), InputError> {
+    if is_domain(id) || is_address_literal(id) {
+        Ok(())
+    } else {
+        Err(InputError::ClientId)
+    }
+}
+
+pub(crate) fn check_address(address: &str) -> Result<(), InputError> {
+    let valid = address.rsplit_once('@').is_some_and(|(local, domain)| {
+        (is_dot_atom(local) || is_quoted_string(local))
+            && (is_domain(domain) || is_address_literal(domain))
+    });
+
+    if valid {
+        Ok(())
+    } else {
+        Err(InputError::Address)
+    }
+}
+
+fn check_boundary(boundary: &str) -> Result<(), InputError> {
+    let valid = (1..=70).contains(&boundary.len())
+        && !boundary.ends_with(' ')
+        && boundary
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "'()+_,-./:=? ".contains(c));
+
+    if valid {
+        Ok(())
+    } else {
+        Err(InputError::Boundary)
+    }
+}
+
+impl Mailbox<'_> {
+    pub fn validate(&self) -> Result<(), InputError> {
+        if let Some(name) = self.name {
+            check_header_value(name)?;
+        }
+        check_address(self.address)
+    }
+}
+
+impl<S, I> Envelope<'_, S, I>
+where
+    S: AsRef<str>,
+    I: Iterator<Item = S> + Clone,
+{
+    pub fn validate(&self) -> Result<(), InputError> {
+        if let Some(sender) = self.sender_addr {
+            check_address(sender)?;
+        }
+        self.receiver_addrs
+            .clone()
+            .try_for_each(|addr| check_address(addr.as_ref()))
+    }
+}
+
+impl Headers<'_> {
+    pub fn validate(&self) -> Result<(), InputError> {
+        match self.date {
+            Some(Date::Text(date)) => check_header_value(date)?,
+            Some(Date::Time(time)) => time.validate()?,
+            Some(Date::Now(clock)) => clock.now().validate()?,
+            None => {}
+        }
+        if let Some(MessageId::Text(id)) = self.message_id {
+            check_header_value(id)?;
+        }
+        if let Some(sender) = self.sender {
+            sender.validate()?;
+        }
+        if let Some(reply_to) = self.reply_to {
+            reply_to.validate()?;
+        }
+        if let Some(in_reply_to) = self.in_reply_to {
+            check_header_value(in_reply_to)?;
+        }
+        self.references
+            .iter()
+            .try_for_each(|reference| check_header_value(reference))?;
+        self.custom.iter().try_for_each(|(name, value)| {
+            check_header_name(name)?;
+            check_header_value(value)
+        })
+    }
+}
+
+impl Multipart<'_> {
+    /// Check the content types and boundaries of the parts, written as header fields (generated
+    /// boundaries and attachment filenames are always valid).
+    pub fn validate(&self) -> Result<(), InputError> {
+        check_header_value(self.subtype)?;
+        if let Boundary::Text(boundary) = self.boundary {
+            check_boundary(boundary)?;
+        }
+        self.parts.iter().try_for_each(|part| match part {
+            Entity::Part(part) => {
+                check_header_value(part.content_type)?;
+                if let Some(charset) = part.charset {
+                    check_header_value(charset)?;
+                }
+                Ok(())
+            }
+            Entity::Multipart(multipart) => multipart.validate(),
+            // the filename is always quoted or percent encoded
+            Entity::Attachment(attachment) => check_header_value(attachment.content_type),
+        })
+    }
+}
+
+impl<'a, Mb, To, Cc, Bcc> Mail<'a, Mb, To, Cc, Bcc>
+where
+    Mb: AsRef<Mailbox<'a>>,
+    To: Iterator<Item = Mb> + Clone,
+    Cc: Iterator<Item = Mb> + Clone,
+    Bcc: Iterator<Item = Mb> + Clone,
+{
+    /// Check the header fields and addresses of the mail, as done by `send` before anything is
+    /// sent.
+    pub fn validate(&self) -> Result<(), InputError> {
+        self.validate_headers()?;
+        self.bcc.clone().try_for_each(|m| m.as_ref().validate())
+    }
+}
+
+impl<'a, Mb, To, Cc, Bcc> Mail<'a, Mb, To, Cc, Bcc>
+where
+    Mb: AsRef<Mailbox<'a>>,
+    To: Iterator<Item = Mb> + Clone,
+    Cc: Iterator<Item = Mb> + Clone,
+    Bcc: Iterator<Item = Mb>,
+{
+    /// Check everything written to the header section.
+    pub(crate) fn validate_headers(&self) -> Result<(), InputError> {
+        if let Some(from) = self.from {
+            from.validate()?;
+        }
+        self.to
+            .clone()
+            .chain(self.cc.clone())
+            .try_for_each(|m| m.as_ref().validate())?;
+        if let Some(subject) = self.subject {
+            check_header_value(subject)?;
+        }
+        if let Some(Body::Multipart(multipart)) = self.body {
+            multipart.validate()?;
+        }
+        self.headers.validate()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::message::Part;
+
+    #[test]
+    fn addresses() {
+        for valid in [
+            "john@example.com",
+            "john.smith+alerts@mail.example.com",
+            "\"john smith\"@example.com",
+            "\"a\\\"b\"@example.com",
+            "plant-3@[192.0.2.1]",
+            "plant-3@[IPv6:2001:db8::1]",
+            "root@localhost",
+        ] {
+            assert_eq!(check_address(valid), Ok(()), "{valid}");
+        }
+
+        for invalid in [
+            "",
+            "john",
+            "@example.com",
+            "john@",
+            "john..smith@example.com",
+            ".john@example.com",
+            "john smith@example.com",
+            "john@example.com>\r\nRCPT TO:<eve@example.com",
+            "john@exa_mple.com",
+            "john@-example.com",
+            "john@example..com",
+            "john@[192.0.2.256]",
+            "john@[IPv6:2001:db8::g]",
+            "\"john\r\n\"@example.com",
+            "\"john\"smith\"@example.com",
+        ] {
+            assert_eq!(
+                check_address(invalid),
+                Err(InputError::Address),
+                "{invalid:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn client_ids() {
+        for valid in [
+            "localhost",
+            "client.example.com",
+            "[192.0.2.1]",
+            "[IPv6:::1]",
+            "[x-tag:opaque]",
+        ] {
+            assert_eq!(check_client_id(valid), Ok(()), "{valid}");
+        }
+
+        for invalid in [
+            "",
+            "client example",
+            "client.example.com\r\nMAIL FROM:<>",
+            "192.0.2.1]",
+            "[192.0.2]",
+            "[x-tag:]",
+            "[x-tag:a]b]",
+        ] {
+            assert_eq!(
+                check_client_id(invalid),
+                Err(InputError::ClientId),
+                "{invalid:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn boundaries() {
+        let long = "b".repeat(70);
+        for valid in [
+            "b",
+            "=_mailr_00000007",
+            "simple boundary",
+            "(a+b),-./:=?'_",
+            &long,
+        ] {
+            assert_eq!(check_boundary(valid), Ok(()), "{valid}");
+        }
+
+        let too_long = "b".repeat(71);
+        for invalid in ["", "b ", "b\"", "b\r\n", "b;", "ü", &too_long] {
+            assert_eq!(
+                check_boundary(invalid),
+                Err(InputError::Boundary),
+                "{invalid:?}"
+            );
+        }
+
+        let inner = [Part::text("Hi").into()];
+        let parts = [Multipart::alternative(&inner, "b\r\n").into()];
+        let multipart = Multipart::new("mixed", &parts, "a");
+        assert_eq!(multipart.validate(), Err(InputError::Boundary));
+    }
+
+    #[test]
+    fn header_injection() {
+        let mail = Mail::new().subject("Hi\r\nBcc: eve@example.com");
+        assert_eq!(mail.validate(), Err(InputError::HeaderValue));
+
+        let from = Mailbox::with_name("John\nX-Evil: 1", "john@example.com");
+        assert_eq!(
+            Mail::new().from(from).validate(),
+            Err(InputError::HeaderValue)
+        );
+
+        let custom = [("X-Priority", "1\0")];
+        let mail = Mail::new().custom_headers(&custom);
+        assert_eq!(mail.validate(), Err(InputError::HeaderValue));
+
+        for name in ["", "X Priority", "X-Priority:", "X-Priorité"] {
+            let custom = [(name, "1")];
+            let mail = Mail::new().custom_headers(&custom);
+            assert_eq!(mail.validate(), Err(InputError::HeaderName), "{name:?}");
+        }
+
+        let parts = [Part::new("text/plain\r\nX-Evil: 1", "Hi").into()];
+        let mail = Mail::new().multipart(Multipart::alternative(&parts, "b"));
+        assert_eq!(mail.validate(), Err(InputError::HeaderValue));
+
+        let bcc = ["eve@example.com\r\nDATA".into()];
+        assert_eq!(Mail::new().bcc(&bcc).validate(), Err(InputError::Address));
+
+        let to = [Mailbox::with_name("Plant 3, Line A", "plant3@example.com")];
+        let mail = Mail::new()
+            .from("john@example.com")
+            .to(&to)
+            .subject("Grüße")
+            .custom_headers(&[("X-Priority", "1")]);
+        assert_eq!(mail.validate(), Ok(()));
+    }
+}
@@ -1,38 +1,204 @@
-use embedded_nal::TcpClientStack;
-use enumset::EnumSet;
+use core::fmt::Debug;
+
+use embedded_nal::nb::block;
 
 use super::{
-    extensions::{EhloInfo, SmtpExtension},
-    response::{ReplyLine, ResponseParser},
-    ConnectError, SendError,
+    dot_stuff::{DataError, DotStuffWriter},
+    extensions::EhloInfo,
+    fold::FoldingWriter,
+    response::{ReplyLine, ResponseError, ResponseParser},
+    ConnectError, Protocol, SendError,
 };
 use crate::{
-    io::{BufWriter, TcpStream, WithBuf, Write},
-    message::{Mail, Mailbox},
+    io::{BufReader, BufStream, BufWriter, Read, Write},
+    message::{
+        check_address, check_client_id, Body, Date, EncodedWords, Headers, InputError, Mail,
+        Mailbox, MessageId, MessageIdBuf,
+    },
 };
 
 /// An SMTP command that can be executed (e.g., EHLO, MAIL, RCPT, etc.).
-pub trait Command<T, B>
+///
+/// Implement it for commands not built into this crate (e.g., XCLIENT, ETRN, VRFY), and run them
+/// with `SmtpClientSession::execute`. The command line is written with
+/// `Connection::write_command`, and the reply read with `Connection::read_reply` or
+/// `Connection::expect_reply`.
+pub trait Command<S, B>
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>,
 {
     type Output;
     type Error;
 
-    fn execute(self, stream: &mut WithBuf<TcpStream<T>, B>) -> Result<Self::Output, Self::Error>;
+    fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error>;
+}
+
+/// The connection to the server that commands are executed on, over transport `S` (e.g., a
+/// `TcpStream`).
+pub struct Connection<S, B>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    pub(crate) stream: BufStream<S, B>,
+    /// Left in the middle of the message data, see `is_poisoned`
+    poisoned: bool,
+}
+
+impl<S, B> Connection<S, B>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    pub(crate) fn new(stream: BufStream<S, B>) -> Self {
+        Self {
+            stream,
+            poisoned: false,
+        }
+    }
+
+    pub(crate) fn into_inner(self) -> BufStream<S, B> {
+        self.stream
+    }
+
+    /// Whether the message data was left unterminated (e.g., because the message source failed),
+    /// as the server would take anything sent next as part of the message. Commands then fail with
+    /// `CommandError::Poisoned`, and the connection can only be closed.
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    pub(crate) fn poison(&mut self) {
+        self.poisoned = true;
+    }
+
+    /// Write a command line (e.g., `format_args!("VRFY {}", user)`), terminated with CRLF.
+    // FIXME: Blocking for simplicity
+    pub fn write_command(
+        &mut self,
+        line: core::fmt::Arguments<'_>,
+    ) -> Result<(), CommandError<S::Error>> {
+        if self.poisoned {
+            return Err(CommandError::Poisoned);
+        }
+
+        let mut stream = BufWriter::from(self);
+        stream.write_fmt(line)?;
+        stream.write(b"\r\n")?;
+        Ok(stream.flush()?)
+    }
+
+    /// Read a whole (possibly multiline) reply, passing every line to `on_line`, and return its
+    /// reply code.
+    // FIXME: Blocking for simplicity
+    pub fn read_reply(
+        &mut self,
+        mut on_line: impl FnMut(&ReplyLine),
+    ) -> Result<[u8; 3], CommandError<S::Error>> {
+        if self.poisoned {
+            return Err(CommandError::Poisoned);
+        }
+
+        let mut response = ResponseParser::new(self);
+
+        loop {
+            let line = response.next_line()?;
+            on_line(&line);
+
+            if !line.has_next {
+                return line
+                    .code
+                    .try_into()
+                    .map_err(|_| CommandError::UnexpectedResponse);
+            }
+        }
+    }
+
+    /// Read a whole reply, failing with `CommandError::Rejected` if its code isn't `code`.
+    pub fn expect_reply(&mut self, code: &[u8; 3]) -> Result<(), CommandError<S::Error>> {
+        match self.read_reply(|_| {})? {
+            reply if &reply == code => Ok(()),
+            reply => Err(CommandError::Rejected(reply)),
+        }
+    }
+}
+
+impl<'s, S, B> From<&'s mut Connection<S, B>> for BufReader<'s, S>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    fn from(value: &'s mut Connection<S, B>) -> Self {
+        value.stream.reader()
+    }
+}
+
+impl<'s, S, B> From<&'s mut Connection<S, B>> for BufWriter<'s, S>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    fn from(value: &'s mut Connection<S, B>) -> Self {
+        value.stream.writer()
+    }
+}
+
+#[derive(Debug)]
+pub enum CommandError<E>
+where
+    E: Debug,
+{
+    IoError(E),
+    NoMem,
+    UnexpectedResponse,
+    /// The server replied with another code than expected.
+    Rejected([u8; 3]),
+    /// The connection was left in the middle of the message data (see `Connection::is_poisoned`).
+    Poisoned,
+}
+
+impl<E> From<E> for CommandError<E>
+where
+    E: Debug,
+{
+    fn from(value: E) -> Self {
+        Self::IoError(value)
+    }
+}
+
+impl<E> From<ResponseError<'_, E>> for CommandError<E>
+where
+    E: Debug,
+{
+    fn from(value: ResponseError<'_, E>) -> Self {
+        match value {
+            ResponseError::ReplyCodeError(code) => code
+                .try_into()
+                .map_or(Self::UnexpectedResponse, Self::Rejected),
+            ResponseError::ReadError(e) => Self::IoError(e),
+            ResponseError::NoMem => Self::NoMem,
+            ResponseError::FormatError => Self::UnexpectedResponse,
+        }
+    }
 }
 
 /// Domain or address literal that identifies the client (https://www.rfc-editor.org/rfc/rfc5321#section-4.1.1.1)
+#[derive(Clone, Copy, Debug)]
 #[repr(transparent)]
 pub struct ClientId<'a>(&'a str);
 
 impl<'a> ClientId<'a> {
-    // FIXME: validate input
+    /// Checked with `validate` when greeting the server.
     pub fn new(id: &'a str) -> Self {
         Self(id)
     }
 
+    /// Check the ID is a domain or an address literal, e.g., `[192.0.2.1]`.
+    pub fn validate(&self) -> Result<(), InputError> {
+        check_client_id(self.0)
+    }
+
     pub const fn localhost() -> Self {
         Self("localhost")
     }
@@ -52,181 +218,274 @@ impl core::fmt::Display for ClientId<'_> {
 
 /// EHLO command for greeting and register supported SMTP extensions
 /// (https://www.rfc-editor.org/rfc/rfc5321#section-4.1.1.1).
-pub struct Ehlo<'a>(pub(crate) ClientId<'a>);
+pub struct Ehlo<'a, const E: usize>(pub(crate) ClientId<'a>);
 
-impl<T, B> Command<T, B> for Ehlo<'_>
+impl<S, B, const E: usize> Command<S, B> for Ehlo<'_, E>
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>,
 {
-    type Output = EhloInfo;
-    type Error = ConnectError<T::Error>;
+    type Output = EhloInfo<E>;
+    type Error = ConnectError<S::Error>;
 
-    fn execute(self, stream: &mut WithBuf<TcpStream<T>, B>) -> Result<Self::Output, Self::Error> {
-        let Self(client_id) = self;
+    fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error> {
+        hello(stream, "EHLO", self.0)
+    }
+}
 
-        {
-            let mut stream = BufWriter::from(&mut *stream);
-            write!(stream, "EHLO {}\r\n", client_id)?;
-        }
+/// LHLO command, the LMTP counterpart of EHLO (https://www.rfc-editor.org/rfc/rfc2033#section-4.1).
+pub struct Lhlo<'a, const E: usize>(pub(crate) ClientId<'a>);
 
-        let mut response = ResponseParser::new(stream);
-
-        {
-            // skip first greeting line
-            let ReplyLine {
-                code: b"250",
-                has_next: true,
-                ..
-            } = response.next_line()?
-            else {
-                return Err(ConnectError::UnexpectedResponse);
-            };
-        }
+impl<S, B, const E: usize> Command<S, B> for Lhlo<'_, E>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    type Output = EhloInfo<E>;
+    type Error = ConnectError<S::Error>;
 
-        // process extensions
-        let mut extensions = EnumSet::new();
+    fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error> {
+        hello(stream, "LHLO", self.0)
+    }
+}
 
-        loop {
-            let ReplyLine {
-                code: b"250",
-                text,
-                has_next,
-            } = response.next_line()?
-            else {
-                return Err(ConnectError::UnexpectedResponse);
-            };
-
-            let mut words = text.split(' ');
-
-            let ext = words.next().ok_or(ConnectError::UnexpectedResponse)?;
-
-            extensions |= match ext {
-                "AUTH" => EnumSet::from_iter(words.map(|mech| match mech {
-                    "PLAIN" => SmtpExtension::AuthPlain.into(),
-                    "LOGIN" => SmtpExtension::AuthLogin.into(),
-                    _ => EnumSet::empty(),
-                })),
-                _ => EnumSet::empty(),
-            };
-
-            if !has_next {
-                break;
-            }
-        }
+/// Greet the server with `verb` (EHLO or LHLO) and register the supported extensions.
+fn hello<S, B, const E: usize>(
+    stream: &mut Connection<S, B>,
+    verb: &str,
+    client_id: ClientId,
+) -> Result<EhloInfo<E>, ConnectError<S::Error>>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    client_id.validate().map_err(ConnectError::InvalidInput)?;
+    {
+        let mut stream = BufWriter::from(&mut *stream);
+        write!(stream, "{} {}\r\n", verb, client_id)?;
+    }
 
-        Ok(EhloInfo { extensions })
+    let mut response = ResponseParser::new(stream);
+
+    // first line starts with the server's domain, and is the only one if it has no extensions
+    let ReplyLine {
+        code: b"250",
+        text,
+        mut has_next,
+    } = response.next_line()?
+    else {
+        return Err(ConnectError::UnexpectedResponse);
+    };
+
+    let mut info = EhloInfo::from_greeting(text);
+
+    // process extensions
+    while has_next {
+        let ReplyLine {
+            code: b"250",
+            text,
+            has_next: next,
+        } = response.next_line()?
+        else {
+            return Err(ConnectError::UnexpectedResponse);
+        };
+
+        info.parse_line(text);
+        has_next = next;
     }
+
+    Ok(info)
 }
 
 /// MAIL FROM command.
 pub struct MailFrom<'a>(pub Option<&'a str>);
 
-impl<T, B> Command<T, B> for MailFrom<'_>
+impl<S, B> Command<S, B> for MailFrom<'_>
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>,
 {
     type Output = ();
-    type Error = SendError<T::Error>;
-
-    fn execute(self, stream: &mut WithBuf<TcpStream<T>, B>) -> Result<Self::Output, Self::Error> {
-        let sender = self.0.unwrap_or("");
+    type Error = SendError<S::Error>;
 
-        {
-            let mut stream = BufWriter::from(&mut *stream);
-            write!(stream, "MAIL FROM:<{}>\r\n", sender)?;
+    fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error> {
+        match mail_from(stream, self.0)? {
+            [b'2', b'5', b'0'] => Ok(()),
+            _ => Err(SendError::SendFailed),
         }
-
-        ResponseParser::new(stream).expect_code(b"250")?;
-        Ok(())
     }
 }
 
-/// RCPT TO command.
-pub struct RcptTo<S, I>(pub I)
+/// Send MAIL FROM and return its reply code, e.g. to tell temporary rejections apart.
+pub(crate) fn mail_from<S, B>(
+    stream: &mut Connection<S, B>,
+    sender: Option<&str>,
+) -> Result<[u8; 3], SendError<S::Error>>
 where
-    S: AsRef<str>,
-    I: Iterator<Item = S>;
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    if let Some(sender) = sender {
+        check_address(sender).map_err(SendError::InvalidInput)?;
+    }
 
-impl<T, B, S, I> Command<T, B> for RcptTo<S, I>
+    stream.write_command(format_args!("MAIL FROM:<{}>", sender.unwrap_or("")))?;
+    Ok(ResponseParser::new(stream).next_reply()?)
+}
+
+/// Send RCPT TO for every receiver, passing `on_reply` the index of each one and its reply code
+/// instead of failing when one is rejected.
+pub(crate) fn rcpt_each<S, B, A>(
+    stream: &mut Connection<S, B>,
+    receivers: impl Iterator<Item = A>,
+    mut on_reply: impl FnMut(usize, [u8; 3]) -> Result<(), SendError<S::Error>>,
+) -> Result<(), SendError<S::Error>>
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>,
-    S: AsRef<str>,
-    I: Iterator<Item = S>,
+    A: AsRef<str>,
 {
-    type Output = ();
-    type Error = SendError<T::Error>;
+    for (i, receiver) in receivers.enumerate() {
+        check_address(receiver.as_ref()).map_err(SendError::InvalidInput)?;
+        stream.write_command(format_args!("RCPT TO:<{}>", receiver.as_ref()))?;
+        let code = ResponseParser::new(&mut *stream).next_reply()?;
+        on_reply(i, code)?;
+    }
 
-    fn execute(self, stream: &mut WithBuf<TcpStream<T>, B>) -> Result<Self::Output, Self::Error> {
-        for receiver in self.0 {
-            {
-                let mut stream = BufWriter::from(&mut *stream);
-                write!(stream, "RCPT TO:<{}>\r\n", receiver.as_ref())?;
-            }
-            ResponseParser::new(&mut *stream).expect_code(b"250")?;
-        }
+    Ok(())
+}
 
-        Ok(())
+/// Delivery result of a single recipient, as reported by the server after the message data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeliveryStatus {
+    /// The message was accepted for the recipient.
+    Delivered,
+    /// The message was rejected for the recipient with the given reply code.
+    Failed([u8; 3]),
+}
+
+impl DeliveryStatus {
+    pub(crate) fn from_reply(code: [u8; 3]) -> Self {
+        match code {
+            [b'2', ..] => Self::Delivered,
+            code => Self::Failed(code),
+        }
     }
 }
 
 /// DATA command.
-pub struct Data<M: DataMessage>(pub M);
+///
+/// With SMTP, the single reply after the message data applies to all `recipients`. With LMTP, the
+/// server replies once per accepted recipient (https://www.rfc-editor.org/rfc/rfc2033#section-4.2).
+/// Either way, `on_status` is called with the index and delivery status of every recipient.
+pub struct Data<M, F>
+where
+    M: DataMessage,
+    F: FnMut(usize, DeliveryStatus),
+{
+    pub message: M,
+    pub protocol: Protocol,
+    pub recipients: usize,
+    pub on_status: F,
+}
 
-impl<T, B, M> Command<T, B> for Data<M>
+impl<S, B, M, F> Command<S, B> for Data<M, F>
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>,
     M: DataMessage,
+    F: FnMut(usize, DeliveryStatus),
 {
     type Output = ();
-    type Error = SendError<T::Error>;
+    type Error = SendError<S::Error>;
 
-    fn execute(self, stream: &mut WithBuf<TcpStream<T>, B>) -> Result<Self::Output, Self::Error> {
-        let message = self.0;
+    fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error> {
+        let Self {
+            message,
+            protocol,
+            recipients,
+            mut on_status,
+        } = self;
 
-        BufWriter::from(&mut *stream).write(b"DATA\r\n")?;
+        stream.write_command(format_args!("DATA"))?;
         ResponseParser::new(&mut *stream).expect_code(b"354")?;
 
-        {
-            let mut stream = BufWriter::from(&mut *stream);
-            message.write_to(&mut stream)?;
-            write!(stream, ".\r\n")?;
+        if let Err(e) = write_data(&mut *stream, message) {
+            // the message can't be ended without the server taking it as complete
+            stream.poison();
+            return Err(e.into());
         }
 
-        ResponseParser::new(stream).expect_code(b"250")?;
-        Ok(())
+        read_data_replies(stream, protocol, recipients, |i, code| {
+            on_status(i, DeliveryStatus::from_reply(code))
+        })
     }
 }
 
-/// The message to be written by the DATA command (see `Data` struct).
-pub trait DataMessage {
-    /// Determines how the message is sent. MUST call `write_sanitized` if data is not sanitized.
-    /// MUST ensure that the written message is ended with "\r\n".
-    fn write_to<W: Write>(self, w: &mut BufWriter<W>) -> Result<(), W::Error>;
+/// Write `message` as the message data, ended with a single period.
+fn write_data<S, B>(
+    stream: &mut Connection<S, B>,
+    message: impl DataMessage,
+) -> Result<(), DataError<S::Error>>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    let mut stream = BufWriter::from(stream);
+    let mut data = DotStuffWriter::new(&mut stream);
+    message.write_to(&mut data)?;
+    data.finish()?;
+    Ok(stream.flush()?)
+}
 
-    /// Write data to a writer, escaping lines beginning with a period `.`
-    /// (https://www.rfc-editor.org/rfc/rfc5321#section-4.5.2).
-    fn write_sanitized<W: Write>(w: &mut BufWriter<W>, mut data: &str) -> Result<(), W::Error> {
-        const DELIM: &str = "\r\n.";
+/// Read the replies after the message data, passing `on_reply` the index of the recipient and its
+/// reply code.
+pub(crate) fn read_data_replies<S, B>(
+    stream: &mut Connection<S, B>,
+    protocol: Protocol,
+    recipients: usize,
+    mut on_reply: impl FnMut(usize, [u8; 3]),
+) -> Result<(), SendError<S::Error>>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    let mut response = ResponseParser::new(stream);
 
-        loop {
-            let pos = data.find(DELIM).map(|p| p + DELIM.len() - 1);
-
-            match pos {
-                Some(pos) => {
-                    write!(w, "{}", &data[..=pos])?;
-                    data = &data[pos..];
-                }
-                None => {
-                    write!(w, "{}", data)?;
-                    break;
-                }
-            };
+    match protocol {
+        Protocol::Smtp => {
+            let code = response.next_reply()?;
+            for i in 0..recipients {
+                on_reply(i, code);
+            }
+        }
+        Protocol::Lmtp => {
+            // All replies must be read with the same parser, as the server is likely to send
+            // them at once.
+            for i in 0..recipients {
+                on_reply(i, response.next_reply()?);
+            }
         }
+    }
+
+    Ok(())
+}
 
+/// The message to be written by the DATA command (see `Data` struct).
+pub trait DataMessage {
+    /// Determines how the message is sent. Lines beginning with a period and line endings are
+    /// taken care of by `w`, and the last line doesn't need to be terminated.
+    ///
+    /// Failing with `DataError::MessageFailed` leaves the message unterminated, so it isn't
+    /// delivered truncated (see `Connection::is_poisoned`).
+    fn write_to<W: Write>(
+        self,
+        w: &mut DotStuffWriter<'_, '_, W>,
+    ) -> Result<(), DataError<W::Error>>;
+
+    /// Check the message before anything is sent, e.g., the header fields of a `Mail`, which
+    /// `write_to` would otherwise reject with `DataError::InvalidInput` after DATA.
+    fn validate(&self) -> Result<(), InputError> {
         Ok(())
     }
 }
@@ -234,72 +493,322 @@ pub trait DataMessage {
 impl<'a, Mb, To, Cc, Bcc> DataMessage for Mail<'a, Mb, To, Cc, Bcc>
 where
     Mb: AsRef<Mailbox<'a>>,
-    To: Iterator<Item = Mb>,
-    Cc: Iterator<Item = Mb>,
+    To: Iterator<Item = Mb> + Clone,
+    Cc: Iterator<Item = Mb> + Clone,
     Bcc: Iterator<Item = Mb>,
 {
-    fn write_to<W: Write>(mut self, w: &mut BufWriter<W>) -> Result<(), W::Error> {
-        if let Some(from) = self.from {
-            write!(w, "From:{}\r\n", from)?;
-        }
+    fn validate(&self) -> Result<(), InputError> {
+        self.validate_headers()
+    }
 
-        if let Some(first) = self.to.next() {
-            write!(w, "To:{}", first.as_ref())?;
-            for rcv in self.to {
-                write!(w, ",{}", rcv.as_ref())?;
-            }
-            write!(w, "\r\n")?;
-        }
+    fn write_to<W: Write>(
+        self,
+        w: &mut DotStuffWriter<'_, '_, W>,
+    ) -> Result<(), DataError<W::Error>> {
+        write_mail(self, None, false, w)
+    }
+}
 
-        if let Some(first) = self.cc.next() {
-            write!(w, "Cc:{}", first.as_ref())?;
-            for rcv in self.cc {
-                write!(w, ",{}", rcv.as_ref())?;
-            }
-            write!(w, "\r\n")?;
-        }
+/// A `Mail` as sent by `send`, with what was taken out of it beforehand (see `prepare_mail`).
+#[derive(Clone)]
+pub(crate) struct MailData<'i, M> {
+    pub mail: M,
+    /// Message-ID generated beforehand, e.g., to be returned by `send`
+    pub message_id: Option<&'i str>,
+    /// The Bcc recipients were taken out of `mail` for the envelope, and there were some
+    pub has_bcc: bool,
+}
+
+/// A `Mail` with its Bcc recipients taken out, along with them and its generated Message-ID.
+pub(crate) type PreparedMail<'a, Mb, To, Cc, Bcc> = (
+    Mail<'a, Mb, To, Cc, core::option::IntoIter<Mb>>,
+    Bcc,
+    Option<MessageIdBuf>,
+);
+
+/// Get `mail` ready to be sent: read its clock once (so the date written is the one checked),
+/// check its header fields and every recipient (so nothing is sent if one is invalid), take its
+/// Bcc recipients out, and generate its Message-ID.
+pub(crate) fn prepare_mail<'a, Mb, To, Cc, Bcc, E>(
+    mut mail: Mail<'a, Mb, To, Cc, Bcc>,
+) -> Result<PreparedMail<'a, Mb, To, Cc, Bcc>, SendError<E>>
+where
+    Mb: AsRef<Mailbox<'a>>,
+    To: Iterator<Item = Mb> + Clone,
+    Cc: Iterator<Item = Mb> + Clone,
+    Bcc: Iterator<Item = Mb> + Clone,
+    E: Debug,
+{
+    if let Some(Date::Now(clock)) = mail.headers.date {
+        mail.headers.date = Some(Date::Time(clock.now()));
+    }
+    mail.validate().map_err(SendError::InvalidInput)?;
+
+    let (mail, bcc) = mail.replace_bcc(None);
+    let message_id = match mail.headers.message_id {
+        Some(MessageId::Generate(source)) => Some(source.next_id().ok_or(SendError::NoMem)?),
+        _ => None,
+    };
+
+    Ok((mail, bcc, message_id))
+}
+
+/// Write the header field `name` with `value`, folded if too long.
+fn write_header<W: Write>(
+    w: &mut DotStuffWriter<'_, '_, W>,
+    name: &str,
+    value: impl core::fmt::Display,
+) -> Result<(), DataError<W::Error>> {
+    let mut header = FoldingWriter::new(w, name)?;
+    write!(header, "{}", value)?;
+    header.finish()
+}
+
+/// Write the `name` header with the comma separated `list`, if not empty, returning whether it
+/// was written.
+fn write_address_list<'a, Mb, W>(
+    w: &mut DotStuffWriter<'_, '_, W>,
+    name: &str,
+    mut list: impl Iterator<Item = Mb>,
+) -> Result<bool, DataError<W::Error>>
+where
+    Mb: AsRef<Mailbox<'a>>,
+    W: Write,
+{
+    let Some(first) = list.next() else {
+        return Ok(false);
+    };
+
+    let mut header = FoldingWriter::new(w, name)?;
+    write!(header, "{}", first.as_ref())?;
+    for mailbox in list {
+        write!(header, ", {}", mailbox.as_ref())?;
+    }
+    header.finish()?;
+    Ok(true)
+}
+
+impl<'a, Mb, To, Cc, Bcc> DataMessage for MailData<'_, Mail<'a, Mb, To, Cc, Bcc>>
+where
+    Mb: AsRef<Mailbox<'a>>,
+    To: Iterator<Item = Mb> + Clone,
+    Cc: Iterator<Item = Mb> + Clone,
+    Bcc: Iterator<Item = Mb>,
+{
+    fn write_to<W: Write>(
+        self,
+        w: &mut DotStuffWriter<'_, '_, W>,
+    ) -> Result<(), DataError<W::Error>> {
+        write_mail(self.mail, self.message_id, self.has_bcc, w)
+    }
+}
+
+/// Write `mail`, with `generated_id` as its Message-ID if any. Nothing is written if its header
+/// fields are invalid.
+fn write_mail<'a, Mb, To, Cc, Bcc, W>(
+    mut mail: Mail<'a, Mb, To, Cc, Bcc>,
+    generated_id: Option<&str>,
+    has_bcc: bool,
+    w: &mut DotStuffWriter<'_, '_, W>,
+) -> Result<(), DataError<W::Error>>
+where
+    Mb: AsRef<Mailbox<'a>>,
+    To: Iterator<Item = Mb> + Clone,
+    Cc: Iterator<Item = Mb> + Clone,
+    Bcc: Iterator<Item = Mb>,
+    W: Write,
+{
+    mail.validate_headers().map_err(DataError::InvalidInput)?;
+
+    let Headers {
+        date,
+        message_id,
+        sender,
+        reply_to,
+        in_reply_to,
+        references,
+        custom,
+    } = mail.headers;
+
+    if let Some(date) = date {
+        write_header(w, "Date", date)?;
+    }
+
+    if let Some(from) = mail.from {
+        write_header(w, "From", from)?;
+    }
+
+    if let Some(sender) = sender {
+        write_header(w, "Sender", sender)?;
+    }
+
+    if let Some(reply_to) = reply_to {
+        write_header(w, "Reply-To", reply_to)?;
+    }
 
-        if let Some(subject) = self.subject {
-            write!(w, "Subject:{}\r\n", subject)?;
+    let mut has_recipients = write_address_list(w, "To", mail.to)?;
+    has_recipients |= write_address_list(w, "Cc", mail.cc)?;
+    if !has_recipients && (has_bcc || mail.bcc.next().is_some()) {
+        // keep the Bcc recipients hidden (https://www.rfc-editor.org/rfc/rfc5322#section-3.6.3)
+        write_header(w, "To", "undisclosed-recipients:;")?;
+    }
+
+    let generated_now;
+    let message_id = match (generated_id, message_id) {
+        (Some(id), _) | (None, Some(MessageId::Text(id))) => Some(id),
+        (None, Some(MessageId::Generate(source))) => {
+            generated_now = source.next_id();
+            generated_now.as_ref().map(MessageIdBuf::as_str)
         }
+        (None, None) => None,
+    };
+    if let Some(message_id) = message_id {
+        write_header(w, "Message-ID", format_args!("<{}>", message_id))?;
+    }
 
-        if let Some(body) = self.body {
-            write!(w, "\r\n")?;
-            Self::write_sanitized(w, body)?;
+    if let Some(in_reply_to) = in_reply_to {
+        write_header(w, "In-Reply-To", format_args!("<{}>", in_reply_to))?;
+    }
 
-            if !body.ends_with("\r\n") {
-                write!(w, "\r\n")?;
-            }
+    if let Some((first, rest)) = references.split_first() {
+        let mut header = FoldingWriter::new(w, "References")?;
+        write!(header, "<{}>", first)?;
+        for reference in rest {
+            write!(header, " <{}>", reference)?;
         }
+        header.finish()?;
+    }
 
-        Ok(())
+    match mail.subject {
+        Some(subject) if !subject.is_ascii() => write_header(w, "Subject", EncodedWords(subject))?,
+        Some(subject) => write_header(w, "Subject", subject)?,
+        None => {}
+    }
+
+    for (name, value) in custom {
+        write_header(w, name, value)?;
     }
+
+    match mail.body {
+        // the multipart header fields end the header section
+        Some(Body::Multipart(multipart)) => write!(w, "MIME-Version:1.0\r\n{}", multipart)?,
+        Some(body) => write!(w, "\r\n{}", body)?,
+        None => {}
+    }
+
+    Ok(())
 }
 
 impl DataMessage for &str {
-    fn write_to<W: Write>(self, w: &mut BufWriter<W>) -> Result<(), W::Error> {
-        Self::write_sanitized(w, self)?;
+    fn write_to<W: Write>(
+        self,
+        w: &mut DotStuffWriter<'_, '_, W>,
+    ) -> Result<(), DataError<W::Error>> {
+        Ok(w.write_str(self)?)
+    }
+}
 
-        if !self.ends_with("\r\n") {
-            write!(w, "\r\n")?;
-        }
+/// A raw message pulled chunk by chunk from an iterator (e.g., reading a log from flash, or
+/// `core::iter::from_fn` for a callback), so it doesn't need to fit in memory. Chunks may be split
+/// anywhere, even within a line ending.
+///
+/// The message ends when the iterator does. If it yields an error instead, the send fails with
+/// `SendError::MessageFailed` rather than delivering the message truncated.
+pub struct MessageChunks<I>(pub I);
 
+impl<I, C, E> DataMessage for MessageChunks<I>
+where
+    I: Iterator<Item = Result<C, E>>,
+    C: AsRef<[u8]>,
+{
+    fn write_to<W: Write>(
+        self,
+        w: &mut DotStuffWriter<'_, '_, W>,
+    ) -> Result<(), DataError<W::Error>> {
+        for chunk in self.0 {
+            let chunk = chunk.map_err(|_| DataError::MessageFailed)?;
+            w.write(chunk.as_ref())?;
+        }
         Ok(())
     }
 }
 
+/// A raw message read from `reader` (e.g., a file, or a log in flash) through `buffer`, so it
+/// doesn't need to fit in memory.
+///
+/// The message ends when the reader does (i.e., reads nothing), so `buffer` must not be empty. If
+/// the reader fails, the send fails with `SendError::MessageFailed` rather than delivering the
+/// message truncated.
+pub struct MessageReader<'b, R>
+where
+    R: Read,
+{
+    reader: R,
+    buffer: &'b mut [u8],
+}
+
+impl<'b, R> MessageReader<'b, R>
+where
+    R: Read,
+{
+    pub fn new(reader: R, buffer: &'b mut [u8]) -> Self {
+        Self { reader, buffer }
+    }
+}
+
+impl<R> DataMessage for MessageReader<'_, R>
+where
+    R: Read,
+{
+    // FIXME: Blocking for simplicity
+    fn write_to<W: Write>(
+        mut self,
+        w: &mut DotStuffWriter<'_, '_, W>,
+    ) -> Result<(), DataError<W::Error>> {
+        loop {
+            match block!(self.reader.read(self.buffer)) {
+                Ok(0) => return Ok(()),
+                Ok(n) => w.write(&self.buffer[..n])?,
+                Err(_) => return Err(DataError::MessageFailed),
+            }
+        }
+    }
+}
+
+/// RSET command, aborting the current mail transaction.
+pub struct Rset;
+
+impl<S, B> Command<S, B> for Rset
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    type Output = ();
+    type Error = CommandError<S::Error>;
+
+    fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error> {
+        stream.write_command(format_args!("RSET"))?;
+        stream.expect_reply(b"250")
+    }
+}
+
 /// QUIT command
 pub struct Quit;
 
-impl<T, B> Command<T, B> for Quit
+impl<S, B> Command<S, B> for Quit
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>,
 {
     type Output = ();
-    type Error = T::Error;
+    type Error = S::Error;
 
-    fn execute(self, stream: &mut WithBuf<TcpStream<T>, B>) -> Result<Self::Output, Self::Error> {
+    /// Nothing is sent on a poisoned connection (see `Connection::is_poisoned`), which can only
+    /// be closed.
+    fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error> {
+        if stream.poisoned {
+            return Ok(());
+        }
         BufWriter::from(stream).write(b"QUIT\r\n")
     }
 }
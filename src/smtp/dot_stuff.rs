@@ -0,0 +1,236 @@
+use core::fmt::Debug;
+
+use crate::{
+    io::{write_fmt_with, BufWriter, Write},
+    message::InputError,
+};
+
+/// Error writing the message data (see `DataMessage`).
+#[derive(Debug)]
+pub enum DataError<E>
+where
+    E: Debug,
+{
+    IoError(E),
+    /// The message source failed (e.g., a reader, or a `Display` implementation returning an
+    /// error), so the message must not be ended.
+    MessageFailed,
+    /// A header field of the message would inject others (see `DataMessage::validate`), so the
+    /// message must not be ended.
+    InvalidInput(InputError),
+}
+
+impl<E> From<E> for DataError<E>
+where
+    E: Debug,
+{
+    fn from(value: E) -> Self {
+        Self::IoError(value)
+    }
+}
+
+impl<E> DataError<E>
+where
+    E: Debug,
+{
+    /// Map the error of `write_fmt_with`, where `None` means formatting failed on its own.
+    pub(crate) fn from_fmt(error: Option<E>) -> Self {
+        error.map_or(Self::MessageFailed, Self::IoError)
+    }
+}
+
+/// Writer of the message data following the DATA command, escaping lines beginning with a period
+/// `.` (https://www.rfc-editor.org/rfc/rfc5321#section-4.5.2), and turning bare `\r` and `\n` into
+/// `\r\n` (https://www.rfc-editor.org/rfc/rfc5321#section-2.3.8).
+///
+/// The state is kept between writes, so the data can be written in chunks split anywhere.
+pub struct DotStuffWriter<'w, 'a, W>
+where
+    W: Write,
+{
+    writer: &'w mut BufWriter<'a, W>,
+    state: DotStuffState,
+}
+
+/// Where a `DotStuffWriter` is in the data, to resume writing with another one.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DotStuffState {
+    line_start: bool,
+    /// The last byte written was a `\r`, already written as `\r\n`
+    after_cr: bool,
+}
+
+impl Default for DotStuffState {
+    fn default() -> Self {
+        Self {
+            line_start: true,
+            after_cr: false,
+        }
+    }
+}
+
+impl<'w, 'a, W> DotStuffWriter<'w, 'a, W>
+where
+    W: Write,
+{
+    pub fn new(writer: &'w mut BufWriter<'a, W>) -> Self {
+        Self::resume(writer, DotStuffState::default())
+    }
+
+    pub(crate) fn resume(writer: &'w mut BufWriter<'a, W>, state: DotStuffState) -> Self {
+        Self { writer, state }
+    }
+
+    pub(crate) fn state(&self) -> DotStuffState {
+        self.state
+    }
+
+    // FIXME: Blocking for now for simplicity
+    pub fn write(&mut self, data: &[u8]) -> Result<(), W::Error> {
+        // start of the block of bytes to be written as is
+        let mut start = 0;
+
+        for (i, &byte) in data.iter().enumerate() {
+            if core::mem::take(&mut self.state.after_cr) && byte == b'\n' {
+                self.writer.write(&data[start..i])?;
+                start = i + 1;
+                continue;
+            }
+
+            match byte {
+                b'.' if self.state.line_start => {
+                    self.writer.write(&data[start..i])?;
+                    self.writer.write(b".")?;
+                    // the period itself is written with the next block
+                    start = i;
+                    self.state.line_start = false;
+                }
+                b'\r' | b'\n' => {
+                    self.writer.write(&data[start..i])?;
+                    self.writer.write(b"\r\n")?;
+                    start = i + 1;
+                    self.state.line_start = true;
+                    self.state.after_cr = byte == b'\r';
+                }
+                _ => self.state.line_start = false,
+            }
+        }
+
+        self.writer.write(&data[start..])
+    }
+
+    #[inline]
+    pub fn write_str(&mut self, data: &str) -> Result<(), W::Error> {
+        self.write(data.as_bytes())
+    }
+
+    /// Writes a formatted string into this writer, returning any error encountered.
+    pub fn write_fmt(&mut self, fmt: core::fmt::Arguments<'_>) -> Result<(), DataError<W::Error>> {
+        write_fmt_with(fmt, |s| self.write_str(s)).map_err(DataError::from_fmt)
+    }
+
+    /// End the message data, terminating the last line if needed, followed by the line with a
+    /// single period.
+    pub fn finish(self) -> Result<(), W::Error> {
+        if !self.state.line_start {
+            self.writer.write(b"\r\n")?;
+        }
+        self.writer.write(b".\r\n")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::io::mock::Replay;
+
+    /// Write `chunks` as message data, returning what's sent.
+    fn stuff<'c>(chunks: impl IntoIterator<Item = &'c [u8]>) -> Vec<u8> {
+        let mut sink = Replay::sink();
+        let mut buf = [0; 16];
+        let mut writer = BufWriter::new(&mut sink, &mut buf);
+        let mut stuffer = DotStuffWriter::new(&mut writer);
+        for chunk in chunks {
+            stuffer.write(chunk).unwrap();
+        }
+        stuffer.finish().unwrap();
+        drop(writer);
+        sink.written
+    }
+
+    /// Read message data back as a server would, checking it's well formed.
+    fn unstuff(data: &[u8]) -> Vec<u8> {
+        let body = data
+            .strip_suffix(b".\r\n")
+            .expect("terminated by a period line");
+        let mut message = Vec::new();
+        for line in body.split_inclusive(|&b| b == b'\n') {
+            let line = line.strip_suffix(b"\r\n").expect("CRLF line ending");
+            assert!(!line.contains(&b'\r') && !line.contains(&b'\n'));
+            assert_ne!(line, b".", "early end of data");
+            message.extend_from_slice(line.strip_prefix(b".").unwrap_or(line));
+            message.extend_from_slice(b"\r\n");
+        }
+        message
+    }
+
+    /// The message with line endings normalized, as it should be received.
+    fn normalize(data: &[u8]) -> Vec<u8> {
+        let mut message = Vec::new();
+        let mut bytes = data.iter().peekable();
+        while let Some(&byte) = bytes.next() {
+            match byte {
+                b'\r' => {
+                    bytes.next_if_eq(&&b'\n');
+                    message.extend_from_slice(b"\r\n");
+                }
+                b'\n' => message.extend_from_slice(b"\r\n"),
+                byte => message.push(byte),
+            }
+        }
+        if !message.is_empty() && !message.ends_with(b"\r\n") {
+            message.extend_from_slice(b"\r\n");
+        }
+        message
+    }
+
+    #[test]
+    fn examples() {
+        assert_eq!(stuff([&b""[..]]), b".\r\n");
+        assert_eq!(stuff([&b"Hi"[..]]), b"Hi\r\n.\r\n");
+        assert_eq!(stuff([&b".\r\n"[..]]), b"..\r\n.\r\n");
+        assert_eq!(
+            stuff([&b"a\r\n.b\n.\rc"[..]]),
+            b"a\r\n..b\r\n..\r\nc\r\n.\r\n"
+        );
+        assert_eq!(stuff([&b"a\r"[..], b"\n.", b"b\r\n"]), b"a\r\n..b\r\n.\r\n");
+        assert_eq!(stuff([&b"a\r"[..], b"\r\n"]), b"a\r\n\r\n.\r\n");
+    }
+
+    fn message_data() -> impl Strategy<Value = Vec<u8>> {
+        let byte = prop_oneof![Just(b'.'), Just(b'\r'), Just(b'\n'), any::<u8>()];
+        prop::collection::vec(byte, 0..200)
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(data in message_data(), splits in prop::collection::vec(0..200usize, 0..10)) {
+            let mut splits: Vec<usize> = splits.into_iter().map(|s| s.min(data.len())).collect();
+            splits.sort();
+
+            let mut chunks = Vec::new();
+            let mut start = 0;
+            for split in splits {
+                chunks.push(&data[start..split]);
+                start = split;
+            }
+            chunks.push(&data[start..]);
+
+            let stuffed = stuff(chunks);
+            prop_assert_eq!(&stuffed, &stuff([&data[..]]));
+            prop_assert_eq!(unstuff(&stuffed), normalize(&data));
+        }
+    }
+}
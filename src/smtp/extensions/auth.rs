@@ -1,14 +1,11 @@
-use base64::engine::{general_purpose::STANDARD as BASE64, Engine};
-use core::fmt::Write;
-use embedded_nal::TcpClientStack;
 use enumset::{enum_set, EnumSet};
 
 use super::{EhloInfo, SmtpExtension};
 use crate::{
     auth::Credential,
-    io::{BufWriter, TcpStream, WithBuf},
+    io::{self, Base64Writer, BufWriter, Read},
     smtp::{
-        commands::Command,
+        commands::{Command, Connection},
         response::{ResponseError, ResponseParser},
         ConnectError,
     },
@@ -19,20 +16,20 @@ pub const AUTH_EXTENSION_MASK: EnumSet<SmtpExtension> =
     enum_set!(SmtpExtension::AuthLogin | SmtpExtension::AuthPlain);
 
 /// AUTH command for SMTP authentication extension (https://www.rfc-editor.org/rfc/rfc4954).
-pub struct Auth<'cred, 'ehlo> {
+pub struct Auth<'cred, 'ehlo, const E: usize> {
     pub credential: Credential<'cred>,
-    pub ehlo_info: &'ehlo EhloInfo,
+    pub ehlo_info: &'ehlo EhloInfo<E>,
 }
 
-impl<T, B> Command<T, B> for Auth<'_, '_>
+impl<S, B, const E: usize> Command<S, B> for Auth<'_, '_, E>
 where
-    T: TcpClientStack,
+    S: Read + io::Write,
     B: AsMut<[u8]>,
 {
     type Output = ();
-    type Error = ConnectError<T::Error>;
+    type Error = ConnectError<S::Error>;
 
-    fn execute(self, stream: &mut WithBuf<TcpStream<T>, B>) -> Result<Self::Output, Self::Error> {
+    fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error> {
         let Self {
             credential,
             ehlo_info,
@@ -66,37 +63,31 @@ where
 
 struct AuthPlain<'cred>(Credential<'cred>);
 
-impl<T, B> Command<T, B> for AuthPlain<'_>
+impl<S, B> Command<S, B> for AuthPlain<'_>
 where
-    T: TcpClientStack,
+    S: Read + io::Write,
     B: AsMut<[u8]>,
 {
     type Output = ();
-    type Error = ConnectError<T::Error>;
+    type Error = ConnectError<S::Error>;
 
-    fn execute(self, stream: &mut WithBuf<TcpStream<T>, B>) -> Result<Self::Output, Self::Error> {
+    fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error> {
         let Self(Credential { username, password }) = self;
 
-        // FIXME: The max credential length of 512 octets should be RFC compliant
-        // (https://www.rfc-editor.org/rfc/rfc4616#section-2). But is there another way to
-        // work around using a fixed buffer? E.g., utilize `stream`'s buffer somehow, or
-        // use a base64 in-place encoding implementation to avoid using an intermediate buffer
-        // like this.
-        let mut auth_buffer = heapless::String::<512>::new();
-        write!(auth_buffer, "\0{}\0{}", username, password).map_err(|_| ConnectError::NoMem)?;
-
-        // FIXME: reuse stream buffer somehow.
-        let mut encoded_auth = [0; 1024];
-        let n = BASE64
-            .encode_slice(auth_buffer, &mut encoded_auth)
-            .map_err(|_| ConnectError::NoMem)?;
-
-        let encoded_auth =
-            core::str::from_utf8(&encoded_auth[..n]).map_err(|_| ConnectError::FormatError)?;
-
         {
             let mut stream = BufWriter::from(&mut *stream);
-            write!(stream, "AUTH PLAIN {}\r\n", encoded_auth)?;
+            stream.write(b"AUTH PLAIN ")?;
+
+            // https://www.rfc-editor.org/rfc/rfc4616#section-2
+            let mut encoder = Base64Writer::new(&mut stream);
+            encoder.write(b"\0")?;
+            encoder.write(username.as_bytes())?;
+            encoder.write(b"\0")?;
+            encoder.write(password.as_bytes())?;
+            encoder.finish()?;
+
+            stream.write(b"\r\n")?;
+            stream.flush()?;
         }
 
         ResponseParser::new(stream)
@@ -110,15 +101,15 @@ where
 
 struct AuthLogin<'cred>(Credential<'cred>);
 
-impl<T, B> Command<T, B> for AuthLogin<'_>
+impl<S, B> Command<S, B> for AuthLogin<'_>
 where
-    T: TcpClientStack,
+    S: Read + io::Write,
     B: AsMut<[u8]>,
 {
     type Output = ();
-    type Error = ConnectError<T::Error>;
+    type Error = ConnectError<S::Error>;
 
-    fn execute(self, stream: &mut WithBuf<TcpStream<T>, B>) -> Result<Self::Output, Self::Error> {
+    fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error> {
         let Self(Credential { username, password }) = self;
 
         BufWriter::from(&mut *stream).write(b"AUTH LOGIN\r\n")?;
@@ -132,15 +123,12 @@ where
 
         // username
         {
-            // FIXME: reuse stream buffer somehow.
-            let mut encoded = [0; 512];
-            let n = BASE64
-                .encode_slice(username, &mut encoded)
-                .map_err(|_| ConnectError::NoMem)?;
-
             let mut stream = BufWriter::from(&mut *stream);
-            stream.write(&encoded[..n])?;
+            let mut encoder = Base64Writer::new(&mut stream);
+            encoder.write(username.as_bytes())?;
+            encoder.finish()?;
             stream.write(b"\r\n")?;
+            stream.flush()?;
         }
 
         ResponseParser::new(&mut *stream)
@@ -152,15 +140,12 @@ where
 
         // password
         {
-            // FIXME: reuse stream buffer somehow.
-            let mut encoded = [0; 512];
-            let n = BASE64
-                .encode_slice(password, &mut encoded)
-                .map_err(|_| ConnectError::NoMem)?;
-
             let mut stream = BufWriter::from(&mut *stream);
-            stream.write(&encoded[..n])?;
+            let mut encoder = Base64Writer::new(&mut stream);
+            encoder.write(password.as_bytes())?;
+            encoder.finish()?;
             stream.write(b"\r\n")?;
+            stream.flush()?;
         }
 
         ResponseParser::new(&mut *stream)
@@ -1,6 +1,10 @@
 pub mod auth;
 
 use enumset::{EnumSet, EnumSetType};
+use heapless::String;
+
+/// Default capacity, in bytes, of the table of keywords announced by the server in reply to EHLO.
+pub const DEFAULT_EHLO_CAPACITY: usize = 256;
 
 /// Enum containing all SMTP extension flags.
 #[derive(EnumSetType, Debug)]
@@ -9,7 +13,487 @@ pub enum SmtpExtension {
     AuthLogin,
 }
 
+/// What the server announced in reply to EHLO (https://www.rfc-editor.org/rfc/rfc5321#section-4.1.1.1).
+///
+/// The domain and every keyword line are kept in a table of `N` bytes. Lines that don't fit are
+/// dropped (see `is_truncated`), but `extensions` always reflects every line.
 #[repr(C)]
-pub struct EhloInfo {
+pub struct EhloInfo<const N: usize = DEFAULT_EHLO_CAPACITY> {
     pub extensions: EnumSet<SmtpExtension>,
+    /// The domain, followed by one "KEYWORD params" line per keyword, separated by '\n'.
+    table: String<N>,
+    truncated: bool,
+}
+
+impl<const N: usize> EhloInfo<N> {
+    /// Start from the first line of the EHLO reply, which begins with the server's domain.
+    pub(crate) fn from_greeting(text: &str) -> Self {
+        let domain = text.split_ascii_whitespace().next().unwrap_or("");
+
+        let mut table = String::new();
+        // a domain that doesn't fit is dropped, leaving room for the keywords
+        let truncated = table.push_str(domain).is_err();
+
+        Self {
+            extensions: EnumSet::new(),
+            table,
+            truncated,
+        }
+    }
+
+    /// Record a keyword line of the EHLO reply (https://www.rfc-editor.org/rfc/rfc5321#section-4.1.1.1).
+    ///
+    /// Keywords are matched ignoring case and parameters may be separated by any amount of
+    /// whitespace. The legacy `AUTH=LOGIN PLAIN` form sent by some older servers is merged into
+    /// the `AUTH` keyword. Blank lines are ignored.
+    pub(crate) fn parse_line(&mut self, text: &str) {
+        let mut words = text.split_ascii_whitespace();
+        let Some(keyword) = words.next() else {
+            return;
+        };
+
+        let legacy_auth = keyword
+            .get(..5)
+            .filter(|prefix| prefix.eq_ignore_ascii_case("AUTH="))
+            .map(|_| &keyword[5..]);
+
+        if let Some(first) = legacy_auth {
+            let mechanisms = Some(first).into_iter().chain(words);
+            self.push_auth(mechanisms.filter(|m| !m.is_empty()));
+        } else if keyword.eq_ignore_ascii_case("AUTH") {
+            self.push_auth(words);
+        } else {
+            self.push_line(keyword, words);
+        }
+    }
+
+    fn push_auth<'a>(&mut self, mechanisms: impl Iterator<Item = &'a str> + Clone) {
+        for mechanism in mechanisms.clone() {
+            if mechanism.eq_ignore_ascii_case("PLAIN") {
+                self.extensions |= SmtpExtension::AuthPlain;
+            } else if mechanism.eq_ignore_ascii_case("LOGIN") {
+                self.extensions |= SmtpExtension::AuthLogin;
+            }
+        }
+
+        let merged = self
+            .keyword("AUTH")
+            .map(|existing| self.merge_auth(existing, mechanisms.clone()));
+
+        match merged {
+            Some((table, truncated)) => {
+                self.table = table;
+                self.truncated |= truncated;
+            }
+            None => self.push_line("AUTH", dedup(mechanisms)),
+        }
+    }
+
+    /// Rebuild the table with the AUTH line moved last, holding the mechanisms of both lines.
+    fn merge_auth<'a>(
+        &self,
+        existing: EhloKeyword<'_>,
+        mechanisms: impl Iterator<Item = &'a str> + Clone,
+    ) -> (String<N>, bool) {
+        let mut table = String::new();
+        let _ = table.push_str(self.domain());
+        for k in self.keywords().filter(|k| k.keyword != "AUTH") {
+            append(&mut table, k.keyword, k.params());
+        }
+
+        // the map shortens the mechanisms' lifetime to that of the existing ones
+        #[allow(clippy::map_identity)]
+        let merged = dedup(existing.params().chain(mechanisms.map(|m| m)));
+        let truncated = !append(&mut table, "AUTH", merged);
+        if truncated {
+            append(&mut table, "AUTH", existing.params());
+        }
+
+        (table, truncated)
+    }
+
+    fn push_line<'a>(&mut self, keyword: &str, params: impl Iterator<Item = &'a str> + Clone) {
+        if !append(&mut self.table, keyword, params) {
+            self.truncated = true;
+        }
+    }
+
+    /// The domain the server identified itself with.
+    pub fn domain(&self) -> &str {
+        self.table.split('\n').next().unwrap_or("")
+    }
+
+    /// Every keyword announced by the server, in order.
+    pub fn keywords(&self) -> impl Iterator<Item = EhloKeyword<'_>> {
+        self.table.split('\n').skip(1).map(|line| {
+            let (keyword, params) = line.split_once(' ').unwrap_or((line, ""));
+            EhloKeyword { keyword, params }
+        })
+    }
+
+    /// Look up a keyword, ignoring case.
+    pub fn keyword(&self, keyword: &str) -> Option<EhloKeyword<'_>> {
+        self.keywords()
+            .find(|k| k.keyword.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Whether the server announced `keyword` (e.g., "PIPELINING"), ignoring case.
+    pub fn supports(&self, keyword: &str) -> bool {
+        self.keyword(keyword).is_some()
+    }
+
+    /// The maximum message size accepted by the server (https://www.rfc-editor.org/rfc/rfc1870).
+    /// `None` if the server announced no fixed limit.
+    pub fn max_size(&self) -> Option<u64> {
+        self.keyword("SIZE")?
+            .params()
+            .next()?
+            .parse()
+            .ok()
+            .filter(|&size| size != 0)
+    }
+
+    /// The SASL mechanisms announced with the AUTH keyword (https://www.rfc-editor.org/rfc/rfc4954).
+    pub fn auth_mechanisms(&self) -> impl Iterator<Item = &str> {
+        self.keyword("AUTH").into_iter().flat_map(|k| k.params())
+    }
+
+    /// Whether some keyword lines were dropped because the table was full.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+impl<const N: usize> core::fmt::Debug for EhloInfo<N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EhloInfo")
+            .field("extensions", &self.extensions)
+            .field("domain", &self.domain())
+            .field("keywords", &DebugKeywords(self))
+            .field("truncated", &self.truncated)
+            .finish()
+    }
+}
+
+struct DebugKeywords<'a, const N: usize>(&'a EhloInfo<N>);
+
+impl<const N: usize> core::fmt::Debug for DebugKeywords<'_, N> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_list().entries(self.0.keywords()).finish()
+    }
+}
+
+/// An EHLO keyword and its parameters (e.g., "AUTH" with "PLAIN LOGIN").
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EhloKeyword<'a> {
+    pub keyword: &'a str,
+    pub params: &'a str,
+}
+
+impl<'a> EhloKeyword<'a> {
+    /// The space-separated parameters.
+    pub fn params(&self) -> impl Iterator<Item = &'a str> + Clone {
+        self.params.split_ascii_whitespace()
+    }
+}
+
+/// Append a "KEYWORD params" line to the table, unless it doesn't fit.
+fn append<'a, const N: usize>(
+    table: &mut String<N>,
+    keyword: &str,
+    params: impl Iterator<Item = &'a str> + Clone,
+) -> bool {
+    let len = 1 + keyword.len() + params.clone().map(|p| 1 + p.len()).sum::<usize>();
+    if table.len() + len > N {
+        return false;
+    }
+
+    // can't fail, as the length is checked above
+    let _ = table.push('\n');
+    let _ = table.push_str(keyword);
+    for param in params {
+        let _ = table.push(' ');
+        let _ = table.push_str(param);
+    }
+    true
+}
+
+/// Skip the parameters already seen, ignoring case.
+fn dedup<'a>(
+    params: impl Iterator<Item = &'a str> + Clone,
+) -> impl Iterator<Item = &'a str> + Clone {
+    params
+        .clone()
+        .enumerate()
+        .filter(move |&(i, param)| {
+            !params
+                .clone()
+                .take(i)
+                .any(|p| p.eq_ignore_ascii_case(param))
+        })
+        .map(|(_, param)| param)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Parse the reply lines of an EHLO transcript, as the client would.
+    fn parse<const N: usize>(transcript: &str) -> EhloInfo<N> {
+        let mut lines = transcript.lines().map(|line| &line[4..]);
+        let mut info = EhloInfo::from_greeting(lines.next().unwrap());
+        for line in lines {
+            info.parse_line(line);
+        }
+        info
+    }
+
+    fn keywords<const N: usize>(info: &EhloInfo<N>) -> Vec<&str> {
+        info.keywords().map(|k| k.keyword).collect()
+    }
+
+    const GMAIL: &str = "\
+250-smtp.gmail.com at your service, [203.0.113.7]
+250-SIZE 35882577
+250-8BITMIME
+250-AUTH LOGIN PLAIN XOAUTH2 PLAIN-CLIENTTOKEN OAUTHBEARER XOAUTH
+250-ENHANCEDSTATUSCODES
+250-PIPELINING
+250-CHUNKING
+250 SMTPUTF8";
+
+    #[test]
+    fn gmail() {
+        let info: EhloInfo = parse(GMAIL);
+
+        assert_eq!(info.domain(), "smtp.gmail.com");
+        assert!(!info.is_truncated());
+        assert_eq!(
+            keywords(&info),
+            [
+                "SIZE",
+                "8BITMIME",
+                "AUTH",
+                "ENHANCEDSTATUSCODES",
+                "PIPELINING",
+                "CHUNKING",
+                "SMTPUTF8"
+            ]
+        );
+        assert_eq!(
+            info.keyword("auth"),
+            Some(EhloKeyword {
+                keyword: "AUTH",
+                params: "LOGIN PLAIN XOAUTH2 PLAIN-CLIENTTOKEN OAUTHBEARER XOAUTH"
+            })
+        );
+        assert_eq!(
+            info.extensions,
+            SmtpExtension::AuthPlain | SmtpExtension::AuthLogin
+        );
+    }
+
+    #[test]
+    fn accessors() {
+        let info: EhloInfo = parse(GMAIL);
+
+        assert!(info.supports("PIPELINING"));
+        assert!(info.supports("pipelining"));
+        assert!(!info.supports("STARTTLS"));
+        assert!(!info.supports("PIPE"));
+
+        assert_eq!(info.max_size(), Some(35882577));
+        assert_eq!(
+            info.auth_mechanisms().collect::<Vec<_>>(),
+            [
+                "LOGIN",
+                "PLAIN",
+                "XOAUTH2",
+                "PLAIN-CLIENTTOKEN",
+                "OAUTHBEARER",
+                "XOAUTH"
+            ]
+        );
+    }
+
+    #[test]
+    fn postfix_legacy_auth() {
+        let info: EhloInfo = parse(
+            "\
+250-mail.example.com
+250-PIPELINING
+250-SIZE 10240000
+250-VRFY
+250-ETRN
+250-STARTTLS
+250-AUTH PLAIN LOGIN
+250-AUTH=PLAIN LOGIN
+250-ENHANCEDSTATUSCODES
+250-8BITMIME
+250 DSN",
+        );
+
+        assert!(!info.is_truncated());
+        assert_eq!(
+            keywords(&info),
+            [
+                "PIPELINING",
+                "SIZE",
+                "VRFY",
+                "ETRN",
+                "STARTTLS",
+                "AUTH",
+                "ENHANCEDSTATUSCODES",
+                "8BITMIME",
+                "DSN"
+            ]
+        );
+        assert_eq!(
+            info.auth_mechanisms().collect::<Vec<_>>(),
+            ["PLAIN", "LOGIN"]
+        );
+    }
+
+    #[test]
+    fn exchange_legacy_auth_only() {
+        let info: EhloInfo = parse(
+            "\
+250-EXCH01.corp.example.com Hello [192.0.2.10]
+250-TURN
+250-SIZE
+250-ETRN
+250-PIPELINING
+250-DSN
+250-ENHANCEDSTATUSCODES
+250-8bitmime
+250-BINARYMIME
+250-CHUNKING
+250-VRFY
+250-X-EXPS GSSAPI NTLM LOGIN
+250-X-EXPS=LOGIN
+250-AUTH GSSAPI NTLM
+250-AUTH=LOGIN
+250-X-LINK2STATE
+250-XEXCH50
+250 OK",
+        );
+
+        assert_eq!(info.domain(), "EXCH01.corp.example.com");
+        assert!(info.supports("8BITMIME"));
+        assert_eq!(info.max_size(), None);
+        assert_eq!(
+            info.auth_mechanisms().collect::<Vec<_>>(),
+            ["GSSAPI", "NTLM", "LOGIN"]
+        );
+        assert_eq!(info.extensions, SmtpExtension::AuthLogin);
+        assert_eq!(
+            info.keyword("X-EXPS=LOGIN").map(|k| k.keyword),
+            Some("X-EXPS=LOGIN")
+        );
+    }
+
+    #[test]
+    fn sendmail_legacy_auth_first() {
+        let info: EhloInfo = parse(
+            "\
+250-mx.example.org Hello client.example.net [198.51.100.4], pleased to meet you
+250-ENHANCEDSTATUSCODES
+250-AUTH=LOGIN PLAIN
+250-AUTH DIGEST-MD5 CRAM-MD5 LOGIN PLAIN
+250-DELIVERBY
+250 HELP",
+        );
+
+        assert_eq!(info.domain(), "mx.example.org");
+        assert_eq!(
+            info.auth_mechanisms().collect::<Vec<_>>(),
+            ["LOGIN", "PLAIN", "DIGEST-MD5", "CRAM-MD5"]
+        );
+        assert_eq!(
+            info.extensions,
+            SmtpExtension::AuthPlain | SmtpExtension::AuthLogin
+        );
+    }
+
+    #[test]
+    fn case_and_whitespace() {
+        let info: EhloInfo = parse(
+            "\
+250-mail.example.net   ESMTP
+250-size\t 52428800
+250-auth  plain   Login\t
+250-
+250-   
+250 starttls",
+        );
+
+        assert_eq!(info.domain(), "mail.example.net");
+        assert_eq!(keywords(&info), ["size", "AUTH", "starttls"]);
+        assert!(info.supports("STARTTLS"));
+        assert_eq!(info.max_size(), Some(52428800));
+        assert_eq!(
+            info.auth_mechanisms().collect::<Vec<_>>(),
+            ["plain", "Login"]
+        );
+        assert_eq!(
+            info.extensions,
+            SmtpExtension::AuthPlain | SmtpExtension::AuthLogin
+        );
+    }
+
+    #[test]
+    fn single_line() {
+        let info: EhloInfo = parse("250 relay.example.com");
+
+        assert_eq!(info.domain(), "relay.example.com");
+        assert_eq!(info.keywords().count(), 0);
+        assert!(info.extensions.is_empty());
+        assert_eq!(info.max_size(), None);
+    }
+
+    #[test]
+    fn no_size_limit() {
+        let info: EhloInfo<64> = parse("250-mx.example.com\n250 SIZE");
+        assert_eq!(info.max_size(), None);
+        assert_eq!(info.auth_mechanisms().count(), 0);
+
+        let info: EhloInfo<64> = parse("250-mx.example.com\n250 SIZE 0");
+        assert_eq!(info.max_size(), None, "SIZE 0 means no fixed limit");
+    }
+
+    #[test]
+    fn truncated() {
+        let info: EhloInfo<32> = parse(
+            "\
+250-mx.example.com
+250-PIPELINING
+250-AUTH PLAIN LOGIN CRAM-MD5
+250 8BITMIME",
+        );
+
+        assert!(info.is_truncated());
+        assert_eq!(keywords(&info), ["PIPELINING"]);
+        // extensions reflect every line
+        assert_eq!(
+            info.extensions,
+            SmtpExtension::AuthPlain | SmtpExtension::AuthLogin
+        );
+
+        // merging keeps the previous AUTH line if the merged one doesn't fit
+        let info: EhloInfo<34> = parse(
+            "\
+250-mx.example.com
+250-AUTH PLAIN LOGIN
+250 AUTH=XOAUTH2",
+        );
+        assert!(info.is_truncated());
+        assert_eq!(
+            info.auth_mechanisms().collect::<Vec<_>>(),
+            ["PLAIN", "LOGIN"]
+        );
+
+        let info: EhloInfo<8> = parse("250 mx.example.com");
+        assert!(info.is_truncated());
+        assert_eq!(info.domain(), "");
+    }
 }
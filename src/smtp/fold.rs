@@ -0,0 +1,288 @@
+use heapless::Vec;
+
+use super::dot_stuff::{DataError, DotStuffWriter};
+use crate::{
+    io::{write_fmt_with, Write},
+    message::InputError,
+};
+
+/// Length a header line is folded at, if possible
+/// (https://www.rfc-editor.org/rfc/rfc5322#section-2.1.1).
+pub const MAX_LINE_LEN: usize = 78;
+
+/// Writer of a header field (https://www.rfc-editor.org/rfc/rfc5322#section-2.2), folding its
+/// value at white space with `\r\n` so lines are at most `MAX_LINE_LEN` characters long where
+/// possible (https://www.rfc-editor.org/rfc/rfc5322#section-2.2.3).
+///
+/// The value may already be folded (e.g., between encoded-words), but any `\r` or `\n` must be
+/// followed by white space, so it can't end the header field and inject another one.
+///
+/// Words are held back until their end is known, so `finish` must be called to write the last
+/// one, ending the header field.
+pub struct FoldingWriter<'d, 'w, 'a, W>
+where
+    W: Write,
+{
+    writer: &'d mut DotStuffWriter<'w, 'a, W>,
+    /// Length of the current line, without what's held back
+    column: usize,
+    /// White space before `word`, where the line can be folded
+    space: Option<u8>,
+    /// Right after the colon, where the line can be folded too
+    after_colon: bool,
+    word: Vec<u8, MAX_LINE_LEN>,
+    /// `word` was too long to be held back, and is written as is until its end
+    long_word: bool,
+    /// Last byte of a line break written as is, which must be followed by white space
+    line_break: Option<u8>,
+}
+
+impl<'d, 'w, 'a, W> FoldingWriter<'d, 'w, 'a, W>
+where
+    W: Write,
+{
+    /// Start the header field `name`.
+    pub fn new(writer: &'d mut DotStuffWriter<'w, 'a, W>, name: &str) -> Result<Self, W::Error> {
+        writer.write_str(name)?;
+        writer.write_str(":")?;
+
+        Ok(Self {
+            writer,
+            column: name.len() + 1,
+            space: None,
+            after_colon: true,
+            word: Vec::new(),
+            long_word: false,
+            line_break: None,
+        })
+    }
+
+    /// Fails with `DataError::InvalidInput` if a line break isn't followed by white space.
+    // FIXME: Blocking for now for simplicity
+    pub fn write(&mut self, data: &[u8]) -> Result<(), DataError<W::Error>> {
+        for &byte in data {
+            match (self.line_break, byte) {
+                // already folded, e.g., between encoded-words
+                (None, b'\r' | b'\n') | (Some(b'\r'), b'\n') => {
+                    self.end_word()?;
+                    if let Some(space) = self.space.take() {
+                        self.put(&[space])?;
+                    }
+                    self.writer.write(&[byte])?;
+                    self.column = 0;
+                    self.after_colon = false;
+                    self.line_break = Some(byte);
+                }
+                (Some(_), b' ' | b'\t') => {
+                    self.put(&[byte])?;
+                    self.line_break = None;
+                }
+                (Some(_), _) => return Err(DataError::InvalidInput(InputError::HeaderValue)),
+                (None, b' ' | b'\t') => {
+                    self.end_word()?;
+                    self.after_colon = false;
+                    if let Some(space) = self.space.replace(byte) {
+                        // consecutive white space, folding at the last one is enough
+                        self.put(&[space])?;
+                    }
+                }
+                _ if self.long_word => self.put(&[byte])?,
+                _ => {
+                    if self.word.push(byte).is_err() {
+                        // can't fit on any line, so written as is after folding before it
+                        self.fold_before(usize::MAX)?;
+                        self.long_word = true;
+                        self.put_word()?;
+                        self.put(&[byte])?;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    pub fn write_str(&mut self, data: &str) -> Result<(), DataError<W::Error>> {
+        self.write(data.as_bytes())
+    }
+
+    /// Writes a formatted string into this writer, returning any error encountered.
+    pub fn write_fmt(&mut self, fmt: core::fmt::Arguments<'_>) -> Result<(), DataError<W::Error>> {
+        write_fmt_with(fmt, |s| self.write_str(s))
+            .map_err(|e| e.unwrap_or(DataError::MessageFailed))
+    }
+
+    /// End the header field, writing what's held back and the line ending. Fails like `write` if
+    /// the value ends with a line break.
+    pub fn finish(mut self) -> Result<(), DataError<W::Error>> {
+        if self.line_break.is_some() {
+            return Err(DataError::InvalidInput(InputError::HeaderValue));
+        }
+        self.end_word()?;
+        if let Some(space) = self.space.take() {
+            self.put(&[space])?;
+        }
+        Ok(self.writer.write_str("\r\n")?)
+    }
+
+    fn put(&mut self, data: &[u8]) -> Result<(), W::Error> {
+        self.column += data.len();
+        self.writer.write(data)
+    }
+
+    /// Fold the line before the held back word if it would go past `MAX_LINE_LEN` with `len`
+    /// more characters.
+    fn fold_before(&mut self, len: usize) -> Result<(), W::Error> {
+        let space = usize::from(self.space.is_some());
+        if self.column.saturating_add(space).saturating_add(len) <= MAX_LINE_LEN {
+            return Ok(());
+        }
+
+        if self.after_colon {
+            self.writer.write_str("\r\n ")?;
+            self.column = 1;
+        } else if let Some(space) = self.space {
+            self.writer.write_str("\r\n")?;
+            self.writer.write(&[space])?;
+            self.column = 1;
+            self.space = None;
+        }
+        Ok(())
+    }
+
+    /// Write the held back white space and word.
+    fn put_word(&mut self) -> Result<(), W::Error> {
+        if let Some(space) = self.space.take() {
+            self.put(&[space])?;
+        }
+        self.after_colon = false;
+
+        let word = core::mem::take(&mut self.word);
+        self.put(&word)
+    }
+
+    fn end_word(&mut self) -> Result<(), W::Error> {
+        if core::mem::take(&mut self.long_word) {
+            return Ok(());
+        }
+        if self.word.is_empty() {
+            return Ok(());
+        }
+
+        self.fold_before(self.word.len())?;
+        self.put_word()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::convert::Infallible;
+
+    use proptest::prelude::*;
+
+    use super::*;
+    use crate::io::{mock::Replay, BufWriter};
+
+    /// Write the header field `name` with `value`, returning what's written.
+    fn fold(name: &str, value: &str) -> String {
+        try_fold(name, value).unwrap()
+    }
+
+    fn try_fold(name: &str, value: &str) -> Result<String, DataError<Infallible>> {
+        let mut sink = Replay::sink();
+        let mut buf = [0; 16];
+        let mut writer = BufWriter::new(&mut sink, &mut buf);
+        let mut stuffer = DotStuffWriter::new(&mut writer);
+        let mut header = FoldingWriter::new(&mut stuffer, name)?;
+        header.write_str(value)?;
+        header.finish()?;
+        drop(writer);
+        Ok(String::from_utf8(sink.written).unwrap())
+    }
+
+    #[test]
+    fn examples() {
+        assert_eq!(fold("Subject", "Hi"), "Subject:Hi\r\n");
+        assert_eq!(fold("Subject", " Hi  there "), "Subject: Hi  there \r\n");
+
+        let words = "lorem ipsum dolor sit amet consectetur adipiscing elit sed do eiusmod";
+        assert_eq!(
+            fold("Subject", words),
+            "Subject:lorem ipsum dolor sit amet consectetur adipiscing elit sed do eiusmod\r\n"
+        );
+        assert_eq!(
+            fold("Subject", &format!("{words} tempor")),
+            "Subject:lorem ipsum dolor sit amet consectetur adipiscing elit sed do eiusmod\r\n \
+             tempor\r\n"
+        );
+
+        let long = "x".repeat(80);
+        assert_eq!(fold("X-Id", &long), format!("X-Id:\r\n {long}\r\n"));
+        assert_eq!(
+            fold("X-Id", &format!("a {long} b")),
+            format!("X-Id:a\r\n {long}\r\n b\r\n")
+        );
+
+        // already folded
+        assert_eq!(fold("Subject", "a\r\n b"), "Subject:a\r\n b\r\n");
+        assert_eq!(
+            fold("X-Id", &format!("a\r\n {long}")),
+            format!("X-Id:a\r\n {long}\r\n")
+        );
+    }
+
+    #[test]
+    fn injected_line_breaks() {
+        for value in [
+            "Hi\r\nBcc: eve@example.com",
+            "Hi\nBcc: eve@example.com",
+            "Hi\r\n\r\n body",
+            "Hi\r\r\n there",
+            "Hi\r\n",
+        ] {
+            assert!(
+                matches!(
+                    try_fold("Subject", value),
+                    Err(DataError::InvalidInput(InputError::HeaderValue))
+                ),
+                "{value:?}"
+            );
+        }
+    }
+
+    fn header_value() -> impl Strategy<Value = String> {
+        let char = prop_oneof![
+            4 => prop::char::range('a', 'z'),
+            1 => Just(' '),
+            1 => Just('\t'),
+        ];
+        prop::collection::vec(char, 0..400).prop_map(|chars| chars.into_iter().collect())
+    }
+
+    proptest! {
+        #[test]
+        fn unfold(value in header_value()) {
+            let folded = fold("Subject", &value);
+            let folded = folded.strip_suffix("\r\n").unwrap();
+
+            // trailing white space can't be folded without leaving a line with only white space
+            for line in folded.split("\r\n") {
+                prop_assert!(!line.trim().is_empty(), "white space line in {:?}", folded);
+                prop_assert!(
+                    line.trim_end().len() <= MAX_LINE_LEN || !line[1..].contains([' ', '\t']),
+                    "{:?} could be folded",
+                    line
+                );
+            }
+
+            // https://www.rfc-editor.org/rfc/rfc5322#section-2.2.3
+            let unfolded = folded.replace("\r\n", "");
+            if !folded.starts_with("Subject:\r\n") {
+                prop_assert_eq!(unfolded, format!("Subject:{value}"));
+            } else {
+                prop_assert_eq!(unfolded, format!("Subject: {value}"));
+            }
+        }
+    }
+}
@@ -1,25 +1,68 @@
 mod commands;
+mod dot_stuff;
 mod extensions;
+mod fold;
+mod mx;
 mod response;
+mod transaction;
 
-use core::{fmt::Debug, mem::ManuallyDrop};
+use core::{fmt::Debug, mem::ManuallyDrop, ops::Range};
 use embedded_nal::{nb::block, AddrType, Dns, SocketAddr, TcpClientStack, TcpError};
 
-pub use self::commands::ClientId;
+pub use self::commands::{
+    ClientId, Command, CommandError, Connection, DataMessage, DeliveryStatus, MessageChunks,
+    MessageReader,
+};
+pub use self::dot_stuff::{DataError, DotStuffWriter};
+pub use self::extensions::{EhloInfo, EhloKeyword, SmtpExtension, DEFAULT_EHLO_CAPACITY};
+pub use self::fold::{FoldingWriter, MAX_LINE_LEN};
+pub use self::mx::{MxDelivery, MxDeliveryError, MAX_EXCHANGERS};
+pub use self::response::ReplyLine;
+pub use self::transaction::{DataWriter, FirstRecipient, NoRecipients, Recipients, Transaction};
 use self::{
-    commands::{Command, Data, DataMessage, Ehlo, MailFrom, Quit, RcptTo},
-    extensions::{auth::Auth, EhloInfo},
+    commands::{mail_from, prepare_mail, rcpt_each, Data, Ehlo, Lhlo, MailData, Quit, Rset},
+    extensions::auth::Auth,
     response::{ResponseError, ResponseParser},
 };
 use crate::{
     auth::Credential,
-    io::{TcpStream, WithBuf},
-    message::{Envelope, Mail, Mailbox},
+    io::{BufStream, Read, TcpStream, Write},
+    message::{Envelope, InputError, Mail, Mailbox, MessageIdBuf},
 };
 
+/// Number of recipients of a single LMTP send that can be rejected by RCPT TO, by position (see
+/// `RejectedSet`).
+const MAX_LMTP_RECIPIENTS: usize = 1024;
+
+/// Recipients rejected by an LMTP server, as a bitset of their indices, to map the replies after
+/// the message data back to the accepted ones.
+struct RejectedSet([u32; MAX_LMTP_RECIPIENTS / 32]);
+
+impl RejectedSet {
+    const fn new() -> Self {
+        Self([0; MAX_LMTP_RECIPIENTS / 32])
+    }
+
+    /// Fails if `i` is out of the set's range.
+    fn insert(&mut self, i: usize) -> Result<(), ()> {
+        let word = self.0.get_mut(i / 32).ok_or(())?;
+        *word |= 1 << (i % 32);
+        Ok(())
+    }
+
+    fn contains(&self, i: usize) -> bool {
+        self.0
+            .get(i / 32)
+            .is_some_and(|word| word & (1 << (i % 32)) != 0)
+    }
+}
+
 pub struct SmtpClient;
 
 impl SmtpClient {
+    /// `buffer` is shared for reading replies and writing commands, and should fit the longest
+    /// reply line expected (512 bytes per RFC 5321).
+    #[allow(clippy::new_ret_no_self)]
     pub fn new<'a, T, B>(stack: &'a mut T, buffer: B) -> SmtpClientConnector<'a, T, B>
     where
         T: TcpClientStack,
@@ -28,97 +71,339 @@ impl SmtpClient {
         SmtpClientConnector {
             stack,
             buffer,
-            auth: None,
-            client_id: None,
+            options: SessionOptions::default(),
+        }
+    }
+
+    /// Start a session over an already connected `transport` (e.g., a serial line to a modem),
+    /// instead of a TCP connection. `buffer` is used as with `new`.
+    pub fn from_transport<'a, S, B>(transport: S, buffer: B) -> SmtpTransportConnector<'a, S, B>
+    where
+        S: Read + Write,
+        B: AsMut<[u8]>,
+    {
+        SmtpTransportConnector {
+            transport,
+            buffer,
+            options: SessionOptions::default(),
         }
     }
 }
 
-pub struct SmtpClientConnector<'a, T, B>
+/// The mail transfer protocol spoken with the server.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Protocol {
+    /// Simple Mail Transfer Protocol (https://www.rfc-editor.org/rfc/rfc5321).
+    #[default]
+    Smtp,
+    /// Local Mail Transfer Protocol (https://www.rfc-editor.org/rfc/rfc2033), for delivering
+    /// into a local mail store.
+    Lmtp,
+}
+
+/// `E` is the capacity of the table keeping the server's EHLO keywords (see `EhloInfo`).
+pub struct SmtpClientConnector<'a, T, B, const E: usize = DEFAULT_EHLO_CAPACITY>
 where
     T: TcpClientStack,
     B: AsMut<[u8]>,
 {
     stack: &'a mut T,
     buffer: B,
-    auth: Option<Credential<'a>>,
-    client_id: Option<ClientId<'a>>,
+    options: SessionOptions<'a>,
 }
 
-impl<'a, T, B> SmtpClientConnector<'a, T, B>
+impl<'a, T, B, const E: usize> SmtpClientConnector<'a, T, B, E>
 where
     T: TcpClientStack,
     B: AsMut<[u8]>,
 {
     pub fn with_auth(mut self, value: impl Into<Option<Credential<'a>>>) -> Self {
-        self.auth = value.into();
+        self.options.auth = value.into();
         self
     }
 
     pub fn with_client_id(mut self, value: impl Into<Option<ClientId<'a>>>) -> Self {
-        self.client_id = value.into();
+        self.options.client_id = value.into();
         self
     }
 
-    // FIXME: Blocking for simplicity
-    pub fn connect(
-        self,
-        remote: impl Into<SocketAddr>,
-    ) -> Result<SmtpClientSession<'a, T, B>, ConnectError<T::Error>> {
+    pub fn with_protocol(mut self, value: Protocol) -> Self {
+        self.options.protocol = value;
+        self
+    }
+
+    /// Set the capacity, in bytes, of the table keeping the keywords announced by the server in
+    /// reply to EHLO.
+    pub fn with_ehlo_capacity<const N: usize>(self) -> SmtpClientConnector<'a, T, B, N> {
         let Self {
             stack,
             buffer,
-            auth,
-            client_id,
+            options,
         } = self;
 
-        let stream = TcpStream::new(stack, remote.into()).map_err(|e| ConnectError::IoError(e))?;
-        let stream = WithBuf(stream, buffer);
-        let mut stream = QuitOnDrop(stream);
+        SmtpClientConnector {
+            stack,
+            buffer,
+            options,
+        }
+    }
 
-        // server greeting
-        ResponseParser::new(&mut stream.0).expect_code(b"220")?;
+    // FIXME: Blocking for simplicity
+    pub fn connect(
+        mut self,
+        remote: impl Into<SocketAddr>,
+    ) -> Result<TcpSession<'a, T, B, E>, ConnectError<T::Error>> {
+        let handshake = self.handshake(remote.into())?;
+        Ok(self.into_session(handshake))
+    }
 
-        let client_id = client_id.unwrap_or(ClientId::localhost());
-        let ehlo_info = Ehlo(client_id).execute(&mut stream.0)?;
+    // FIXME: Blocking for simplicity
+    pub fn connect_with_hostname<D>(
+        mut self,
+        dns: &mut D,
+        hostname: &str,
+        port: u16,
+    ) -> Result<TcpSession<'a, T, B, E>, ConnectHostnameError<D::Error, T::Error>>
+    where
+        D: Dns,
+    {
+        let handshake = self.handshake_endpoint(dns, Endpoint::Hostname(hostname, port))?;
+        Ok(self.into_session(handshake))
+    }
 
-        if let Some(credential) = auth {
-            let ehlo_info = &ehlo_info;
+    /// Try connecting to each of the `endpoints` in order, moving on to the next one if the
+    /// connection fails or the server is temporarily unavailable (4xx greeting). Returns the
+    /// session along with the index of the endpoint it is connected to.
+    ///
+    /// Every failure that causes a move to the next endpoint is reported through `on_failure`,
+    /// along with the index of the failed endpoint. Any other failure (e.g., failed
+    /// authentication) aborts immediately.
+    // FIXME: Blocking for simplicity
+    pub fn connect_any<'e, D>(
+        mut self,
+        dns: &mut D,
+        endpoints: impl IntoIterator<Item = Endpoint<'e>>,
+        mut on_failure: impl FnMut(usize, ConnectHostnameError<D::Error, T::Error>),
+    ) -> ConnectAnyResult<'a, T, B, E, D::Error>
+    where
+        D: Dns,
+    {
+        for (index, endpoint) in endpoints.into_iter().enumerate() {
+            match self.handshake_endpoint(dns, endpoint) {
+                Ok(handshake) => return Ok((self.into_session(handshake), index)),
+                Err(error) if error.is_temporary() => on_failure(index, error),
+                Err(error) => return Err(ConnectAnyError::Aborted { index, error }),
+            }
+        }
 
-            Auth {
-                credential,
-                ehlo_info,
+        Err(ConnectAnyError::AllFailed)
+    }
+
+    fn handshake_endpoint<D>(
+        &mut self,
+        dns: &mut D,
+        endpoint: Endpoint,
+    ) -> Result<Handshake<T, E>, ConnectHostnameError<D::Error, T::Error>>
+    where
+        D: Dns,
+    {
+        let remote = match endpoint {
+            Endpoint::Addr(addr) => addr,
+            Endpoint::Hostname(hostname, port) => {
+                let addr = block!(dns.get_host_by_name(hostname, AddrType::Either))
+                    .map_err(ConnectHostnameError::DnsError)?;
+                SocketAddr::new(addr, port)
             }
-            .execute(&mut stream.0)?;
+        };
+
+        Ok(self.handshake(remote)?)
+    }
+
+    /// Connect and go through the greeting and authentication, returning the connected socket on
+    /// success. The stack and buffer are only borrowed so that `self` can be reused to try
+    /// another server on failure.
+    fn handshake(&mut self, remote: SocketAddr) -> Result<Handshake<T, E>, ConnectError<T::Error>> {
+        let stream = TcpStream::new(&mut *self.stack, remote).map_err(ConnectError::IoError)?;
+        let stream = BufStream::new(stream, self.buffer.as_mut());
+        let mut stream = QuitOnDrop(Connection::new(stream));
+
+        let ehlo_info = self.options.handshake(&mut stream.0)?;
+
+        // keep what the server may have sent early, along with the socket
+        let (stream, _, filled) = stream.into_inner().into_inner().into_parts();
+
+        Ok(Handshake {
+            socket: stream.into_socket(),
+            filled,
+            ehlo_info,
+        })
+    }
+
+    fn into_session(self, handshake: Handshake<T, E>) -> TcpSession<'a, T, B, E> {
+        let Handshake {
+            socket,
+            filled,
+            ehlo_info,
+        } = handshake;
+
+        SmtpClientSession {
+            stream: Connection::new(BufStream::from_parts(
+                TcpStream::from_socket(self.stack, socket),
+                self.buffer,
+                filled,
+            )),
+            ehlo_info,
+            protocol: self.options.protocol,
+        }
+    }
+}
+
+/// The session and the index of the endpoint it is connected to.
+type ConnectAnyResult<'a, T, B, const E: usize, DE> =
+    Result<(TcpSession<'a, T, B, E>, usize), ConnectAnyError<DE, <T as TcpClientStack>::Error>>;
+
+/// Connector for a session over a transport other than TCP (see `SmtpClient::from_transport`).
+///
+/// `E` is the capacity of the table keeping the server's EHLO keywords (see `EhloInfo`).
+pub struct SmtpTransportConnector<'a, S, B, const E: usize = DEFAULT_EHLO_CAPACITY>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    transport: S,
+    buffer: B,
+    options: SessionOptions<'a>,
+}
+
+impl<'a, S, B, const E: usize> SmtpTransportConnector<'a, S, B, E>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+{
+    pub fn with_auth(mut self, value: impl Into<Option<Credential<'a>>>) -> Self {
+        self.options.auth = value.into();
+        self
+    }
+
+    pub fn with_client_id(mut self, value: impl Into<Option<ClientId<'a>>>) -> Self {
+        self.options.client_id = value.into();
+        self
+    }
+
+    pub fn with_protocol(mut self, value: Protocol) -> Self {
+        self.options.protocol = value;
+        self
+    }
+
+    /// Set the capacity, in bytes, of the table keeping the keywords announced by the server in
+    /// reply to EHLO.
+    pub fn with_ehlo_capacity<const N: usize>(self) -> SmtpTransportConnector<'a, S, B, N> {
+        let Self {
+            transport,
+            buffer,
+            options,
+        } = self;
+
+        SmtpTransportConnector {
+            transport,
+            buffer,
+            options,
         }
+    }
+
+    /// Go through the greeting and authentication. On failure, the transport is dropped.
+    // FIXME: Blocking for simplicity
+    pub fn connect(self) -> Result<SmtpClientSession<S, B, E>, ConnectError<S::Error>> {
+        let mut stream = QuitOnDrop(Connection::new(BufStream::new(self.transport, self.buffer)));
+        let ehlo_info = self.options.handshake(&mut stream.0)?;
 
         Ok(SmtpClientSession {
             stream: stream.into_inner(),
             ehlo_info,
+            protocol: self.options.protocol,
         })
     }
+}
 
-    // FIXME: Blocking for simplicity
-    pub fn connect_with_hostname<D>(
-        self,
-        dns: &mut D,
-        hostname: &str,
-        port: u16,
-    ) -> Result<SmtpClientSession<'a, T, B>, ConnectHostnameError<D::Error, T::Error>>
+/// What to go through once connected, shared by the connectors.
+#[derive(Default)]
+struct SessionOptions<'a> {
+    auth: Option<Credential<'a>>,
+    client_id: Option<ClientId<'a>>,
+    protocol: Protocol,
+}
+
+impl SessionOptions<'_> {
+    /// Expect the server greeting, then greet with EHLO (or LHLO) and authenticate if needed.
+    fn handshake<S, B, const E: usize>(
+        &self,
+        stream: &mut Connection<S, B>,
+    ) -> Result<EhloInfo<E>, ConnectError<S::Error>>
     where
-        D: Dns,
+        S: Read + Write,
+        B: AsMut<[u8]>,
     {
-        let addr = block!(dns.get_host_by_name(hostname, AddrType::Either))
-            .map_err(|e| ConnectHostnameError::DnsError(e))?;
+        ResponseParser::new(&mut *stream)
+            .expect_code(b"220")
+            .map_err(|e| match e {
+                ResponseError::ReplyCodeError(code @ [b'4' | b'5', _, _]) => {
+                    ConnectError::GreetingRejected([code[0], code[1], code[2]])
+                }
+                e => e.into(),
+            })?;
+
+        let client_id = self.client_id.unwrap_or(ClientId::localhost());
+        let ehlo_info = match self.protocol {
+            Protocol::Smtp => Ehlo(client_id).execute(&mut *stream)?,
+            Protocol::Lmtp => Lhlo(client_id).execute(&mut *stream)?,
+        };
+
+        if let Some(credential) = self.auth {
+            let ehlo_info = &ehlo_info;
+
+            Auth {
+                credential,
+                ehlo_info,
+            }
+            .execute(stream)?;
+        }
+
+        Ok(ehlo_info)
+    }
+}
+
+/// A connection that went through the greeting and authentication.
+struct Handshake<T: TcpClientStack, const E: usize> {
+    socket: T::TcpSocket,
+    /// The part of the buffer read but not consumed yet.
+    filled: Range<usize>,
+    ehlo_info: EhloInfo<E>,
+}
+
+/// A server to connect to.
+#[derive(Clone, Copy, Debug)]
+pub enum Endpoint<'a> {
+    Addr(SocketAddr),
+    /// Hostname to be resolved, and port.
+    Hostname(&'a str, u16),
+}
 
-        Ok(self.connect((addr, port))?)
+impl From<SocketAddr> for Endpoint<'_> {
+    fn from(value: SocketAddr) -> Self {
+        Self::Addr(value)
+    }
+}
+
+impl<'a> From<(&'a str, u16)> for Endpoint<'a> {
+    fn from((hostname, port): (&'a str, u16)) -> Self {
+        Self::Hostname(hostname, port)
     }
 }
 
 #[derive(Debug)]
 pub enum ConnectError<E>
 where
-    E: TcpError,
+    E: Debug,
 {
     IoError(E),
     NoMem,
@@ -126,11 +411,16 @@ where
     AuthFailed,
     AuthUnsupported,
     UnexpectedResponse,
+    /// The server greeted with a 4xx (temporary) or 5xx (permanent) reply instead of accepting
+    /// the connection.
+    GreetingRejected([u8; 3]),
+    /// The client ID was rejected before being sent.
+    InvalidInput(InputError),
 }
 
 impl<'a, E> From<ResponseError<'a, E>> for ConnectError<E>
 where
-    E: TcpError,
+    E: Debug,
 {
     fn from(value: ResponseError<'a, E>) -> Self {
         match value {
@@ -145,7 +435,7 @@ where
 
 impl<E> From<E> for ConnectError<E>
 where
-    E: TcpError,
+    E: Debug,
 {
     fn from(value: E) -> Self {
         Self::IoError(value)
@@ -172,16 +462,49 @@ where
     }
 }
 
+impl<DE, E> ConnectHostnameError<DE, E>
+where
+    DE: Debug,
+    E: TcpError,
+{
+    /// Whether the failure is specific to the server tried, so that another one may succeed.
+    fn is_temporary(&self) -> bool {
+        matches!(
+            self,
+            Self::DnsError(_)
+                | Self::ConnectError(ConnectError::IoError(_))
+                | Self::ConnectError(ConnectError::GreetingRejected([b'4', _, _]))
+        )
+    }
+}
+
+#[derive(Debug)]
+pub enum ConnectAnyError<DE, E>
+where
+    DE: Debug,
+    E: TcpError,
+{
+    /// None of the endpoints could be connected to. Their failures were reported through
+    /// `on_failure`.
+    AllFailed,
+    /// Connecting to the endpoint at `index` failed in a way that trying another one would not
+    /// fix.
+    Aborted {
+        index: usize,
+        error: ConnectHostnameError<DE, E>,
+    },
+}
+
 /// For clean up on `connect` fails.
 /// FIXME: integrate this into `SmtpClientSession` struct would be nice.
-struct QuitOnDrop<'a, T, B>(WithBuf<TcpStream<'a, T>, B>)
+struct QuitOnDrop<S, B>(Connection<S, B>)
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>;
 
-impl<T, B> Drop for QuitOnDrop<'_, T, B>
+impl<S, B> Drop for QuitOnDrop<S, B>
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>,
 {
     fn drop(&mut self) {
@@ -189,12 +512,12 @@ where
     }
 }
 
-impl<'a, T, B> QuitOnDrop<'a, T, B>
+impl<S, B> QuitOnDrop<S, B>
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>,
 {
-    pub fn into_inner(self) -> WithBuf<TcpStream<'a, T>, B> {
+    pub fn into_inner(self) -> Connection<S, B> {
         let me = ManuallyDrop::new(self);
 
         // SAFETY: safe to extract inner as it's never touched again otherwise.
@@ -202,20 +525,25 @@ where
     }
 }
 
+/// A session over TCP, as returned by `SmtpClientConnector`.
+pub type TcpSession<'a, T, B, const E: usize = DEFAULT_EHLO_CAPACITY> =
+    SmtpClientSession<TcpStream<'a, T>, B, E>;
+
+/// A session with the server, over transport `S` (e.g., a `TcpStream`).
 #[repr(C)]
-pub struct SmtpClientSession<'a, T, B>
+pub struct SmtpClientSession<S, B, const E: usize = DEFAULT_EHLO_CAPACITY>
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>,
 {
-    stream: WithBuf<TcpStream<'a, T>, B>,
-    #[allow(unused)]
-    ehlo_info: EhloInfo,
+    stream: Connection<S, B>,
+    ehlo_info: EhloInfo<E>,
+    protocol: Protocol,
 }
 
-impl<'a, T, B> Debug for SmtpClientSession<'a, T, B>
+impl<S, B, const E: usize> Debug for SmtpClientSession<S, B, E>
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>,
 {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
@@ -223,46 +551,83 @@ where
     }
 }
 
-impl<T, B> SmtpClientSession<'_, T, B>
+impl<S, B, const E: usize> SmtpClientSession<S, B, E>
 where
-    T: TcpClientStack,
+    S: Read + Write,
     B: AsMut<[u8]>,
 {
-    fn send_internal<S, I>(
+    /// What the server announced in reply to EHLO (or LHLO).
+    pub fn ehlo_info(&self) -> &EhloInfo<E> {
+        &self.ehlo_info
+    }
+
+    /// Execute a command on the session, e.g., one not built into this crate (see `Command`).
+    pub fn execute<C>(&mut self, command: C) -> Result<C::Output, C::Error>
+    where
+        C: Command<S, B>,
+    {
+        command.execute(&mut self.stream)
+    }
+
+    /// Whether the session was left in the middle of the message data by a failure, and can only
+    /// be closed (see `Connection::is_poisoned`).
+    pub fn is_poisoned(&self) -> bool {
+        self.stream.is_poisoned()
+    }
+
+    /// Start a mail transaction with MAIL FROM, to add recipients and send the message data step
+    /// by step, with the reply of each.
+    pub fn transaction<'a>(
+        &mut self,
+        from: impl Into<Option<&'a str>>,
+    ) -> Result<Transaction<'_, S, B, NoRecipients>, SendError<S::Error>> {
+        Transaction::start(&mut self.stream, self.protocol, from.into())
+    }
+
+    fn send_internal<A, I>(
         &mut self,
-        envelope: Envelope<S, I>,
+        envelope: Envelope<A, I>,
         message: impl DataMessage,
-    ) -> Result<(), SendError<T::Error>>
+        on_status: impl FnMut(usize, DeliveryStatus),
+    ) -> Result<(), SendError<S::Error>>
     where
-        S: AsRef<str>,
-        I: Iterator<Item = S>,
+        A: AsRef<str>,
+        I: Iterator<Item = A>,
     {
-        let stream = &mut self.stream;
+        message.validate().map_err(SendError::InvalidInput)?;
 
         let Envelope {
             sender_addr,
             receiver_addrs,
         } = envelope;
 
-        MailFrom(sender_addr).execute(&mut *stream)?;
-        RcptTo(receiver_addrs).execute(&mut *stream)?;
-        Data(message).execute(stream)
+        send_transaction(
+            &mut self.stream,
+            self.protocol,
+            sender_addr,
+            receiver_addrs,
+            message,
+            false,
+            on_status,
+        )?;
+        Ok(())
     }
 
-    #[inline]
-    pub fn send<'a, Mb, To, Cc, Bcc>(
+    fn send_mail_internal<'a, Mb, To, Cc, Bcc>(
         &mut self,
         mail: Mail<'a, Mb, To, Cc, Bcc>,
-    ) -> Result<(), SendError<T::Error>>
+        on_status: impl FnMut(usize, DeliveryStatus),
+    ) -> Result<Option<MessageIdBuf>, SendError<S::Error>>
     where
         Mb: AsRef<Mailbox<'a>>,
         To: Iterator<Item = Mb> + Clone,
         Cc: Iterator<Item = Mb> + Clone,
-        Bcc: Iterator<Item = Mb>,
+        Bcc: Iterator<Item = Mb> + Clone,
     {
+        let (mail, bcc, generated_id) = prepare_mail(mail)?;
         let sender = mail.from.map(|m| m.address);
 
-        let (mail, bcc) = mail.replace_bcc(None);
+        let has_bcc = bcc.clone().next().is_some();
         let receivers = mail
             .to
             .clone()
@@ -272,36 +637,225 @@ where
 
         let envelope = Envelope::new(sender, receivers);
 
-        self.send_internal(envelope, mail)
+        let mail = MailData {
+            mail,
+            message_id: generated_id.as_ref().map(MessageIdBuf::as_str),
+            has_bcc,
+        };
+        self.send_internal(envelope, mail, on_status)?;
+
+        Ok(generated_id)
     }
 
+    /// Send the mail, failing if it is not delivered to every recipient.
+    ///
+    /// Returns the Message-ID generated for the mail, if any (see `Mail::message_id`).
     #[inline]
-    pub fn send_raw<S, I>(
+    pub fn send<'a, Mb, To, Cc, Bcc>(
         &mut self,
-        envelope: Envelope<S, I>,
-        message: &str,
-    ) -> Result<(), SendError<T::Error>>
+        mail: Mail<'a, Mb, To, Cc, Bcc>,
+    ) -> Result<Option<MessageIdBuf>, SendError<S::Error>>
     where
-        S: AsRef<str>,
-        I: Iterator<Item = S>,
+        Mb: AsRef<Mailbox<'a>>,
+        To: Iterator<Item = Mb> + Clone,
+        Cc: Iterator<Item = Mb> + Clone,
+        Bcc: Iterator<Item = Mb> + Clone,
     {
-        self.send_internal(envelope, message)
+        let mut delivered = true;
+        let message_id = self.send_mail_internal(mail, |_, status| {
+            delivered &= status == DeliveryStatus::Delivered
+        })?;
+
+        delivered.then_some(message_id).ok_or(SendError::SendFailed)
     }
 
-    pub fn close(self) -> Result<(), T::Error> {
-        let mut me = ManuallyDrop::new(self);
-        Quit.execute(&mut me.stream)?;
+    /// Send the mail, reporting the delivery status of each recipient through `on_status` instead
+    /// of failing when some of them are rejected after the message data. Recipients are indexed in
+    /// the order of `to`, `cc`, then `bcc`.
+    ///
+    /// This is mostly useful with LMTP, where the server reports a separate delivery result for
+    /// every recipient. Recipients rejected by RCPT TO are then reported as failed, and the mail is
+    /// still sent to the others. Only the first 1024 recipients can be rejected this way: a
+    /// rejection after them aborts the send with `SendError::NoMem`.
+    ///
+    /// Returns the Message-ID generated for the mail, if any, like `send`.
+    #[inline]
+    pub fn send_with_status<'a, Mb, To, Cc, Bcc>(
+        &mut self,
+        mail: Mail<'a, Mb, To, Cc, Bcc>,
+        on_status: impl FnMut(usize, DeliveryStatus),
+    ) -> Result<Option<MessageIdBuf>, SendError<S::Error>>
+    where
+        Mb: AsRef<Mailbox<'a>>,
+        To: Iterator<Item = Mb> + Clone,
+        Cc: Iterator<Item = Mb> + Clone,
+        Bcc: Iterator<Item = Mb> + Clone,
+    {
+        self.send_mail_internal(mail, on_status)
+    }
+
+    /// Send a raw message (e.g., a `&str`, or `MessageChunks` for one too large to be kept in
+    /// memory), failing if it is not delivered to every recipient.
+    #[inline]
+    pub fn send_raw<A, I>(
+        &mut self,
+        envelope: Envelope<A, I>,
+        message: impl DataMessage,
+    ) -> Result<(), SendError<S::Error>>
+    where
+        A: AsRef<str>,
+        I: Iterator<Item = A>,
+    {
+        let mut delivered = true;
+        self.send_internal(envelope, message, |_, status| {
+            delivered &= status == DeliveryStatus::Delivered
+        })?;
+
+        delivered.then_some(()).ok_or(SendError::SendFailed)
+    }
+
+    /// Same as `send_with_status`, but for a raw message. Recipients are indexed in the order of
+    /// the envelope's receiver addresses.
+    #[inline]
+    pub fn send_raw_with_status<A, I>(
+        &mut self,
+        envelope: Envelope<A, I>,
+        message: impl DataMessage,
+        on_status: impl FnMut(usize, DeliveryStatus),
+    ) -> Result<(), SendError<S::Error>>
+    where
+        A: AsRef<str>,
+        I: Iterator<Item = A>,
+    {
+        self.send_internal(envelope, message, on_status)
+    }
+
+    /// End the session with QUIT, and return the transport (e.g., to close it).
+    pub fn quit(self) -> Result<S, S::Error> {
+        let me = ManuallyDrop::new(self);
 
         // SAFETY: `stream` is behind `ManuallyDrop` and is never touched again
         // so it's safe to convert here.
-        unsafe { core::ptr::read(&me.stream).0.close() }
+        let mut stream = unsafe { core::ptr::read(&me.stream) };
+        Quit.execute(&mut stream)?;
+
+        Ok(stream.into_inner().into_inner())
     }
 }
 
-impl<T, B> Drop for SmtpClientSession<'_, T, B>
+/// Run a whole mail transaction, from MAIL FROM to the message data.
+///
+/// With `defer`, a temporary rejection (4xx reply) of MAIL FROM or of any recipient aborts the
+/// transaction before the message data, and its reply code is returned instead of failing, so the
+/// mail can be sent again later or to another server. Without it, no reply code is ever returned.
+fn send_transaction<S, B, A>(
+    stream: &mut Connection<S, B>,
+    protocol: Protocol,
+    sender: Option<&str>,
+    receiver_addrs: impl Iterator<Item = A>,
+    message: impl DataMessage,
+    defer: bool,
+    on_status: impl FnMut(usize, DeliveryStatus),
+) -> Result<Option<[u8; 3]>, SendError<S::Error>>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+    A: AsRef<str>,
+{
+    match mail_from(&mut *stream, sender)? {
+        [b'2', b'5', b'0'] => {}
+        code @ [b'4', ..] if defer => return Ok(Some(code)),
+        _ => return Err(SendError::SendFailed),
+    }
+
+    // e.g., an invalid receiver address or a rejected DATA leaves the transaction open
+    let result = send_recipients(stream, protocol, receiver_addrs, message, defer, on_status);
+    if !matches!(result, Ok(None)) {
+        let _ = Rset.execute(stream);
+    }
+    result
+}
+
+/// Send RCPT TO for every receiver and the message data, once MAIL FROM was accepted (see
+/// `send_transaction`).
+fn send_recipients<S, B, A>(
+    stream: &mut Connection<S, B>,
+    protocol: Protocol,
+    receiver_addrs: impl Iterator<Item = A>,
+    message: impl DataMessage,
+    defer: bool,
+    mut on_status: impl FnMut(usize, DeliveryStatus),
+) -> Result<Option<[u8; 3]>, SendError<S::Error>>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
+    A: AsRef<str>,
+{
+    // With LMTP, every recipient has its own delivery status, so rejected ones are reported right
+    // away and the message is sent to the others. They are kept to map the replies after the
+    // message data back to the accepted recipients.
+    let mut rejected = RejectedSet::new();
+    let mut recipients = 0;
+    let mut deferred = None;
+    let mut failed = false;
+    rcpt_each(&mut *stream, receiver_addrs, |i, code| {
+        match code {
+            [b'2', ..] => recipients += 1,
+            // a temporary failure takes precedence, as another try may be accepted for everyone
+            [b'4', ..] if defer => deferred = Some(code),
+            _ if protocol == Protocol::Lmtp => {
+                rejected.insert(i).map_err(|()| SendError::NoMem)?;
+                on_status(i, DeliveryStatus::Failed(code));
+            }
+            _ if defer => failed = true,
+            _ => return Err(SendError::SendFailed),
+        }
+        Ok(())
+    })?;
+
+    if deferred.is_some() {
+        return Ok(deferred);
+    }
+    if failed {
+        return Err(SendError::SendFailed);
+    }
+    if protocol == Protocol::Lmtp && recipients == 0 {
+        return Ok(Rset.execute(stream).map(|()| None)?);
+    }
+
+    // index of the next accepted recipient
+    let mut next = 0;
+    Data {
+        message,
+        protocol,
+        recipients,
+        on_status: |_, status| {
+            while rejected.contains(next) {
+                next += 1;
+            }
+            on_status(next, status);
+            next += 1;
+        },
+    }
+    .execute(stream)?;
+
+    Ok(None)
+}
+
+impl<T, B, const E: usize> TcpSession<'_, T, B, E>
 where
     T: TcpClientStack,
     B: AsMut<[u8]>,
+{
+    pub fn close(self) -> Result<(), T::Error> {
+        self.quit()?.close()
+    }
+}
+
+impl<S, B, const E: usize> Drop for SmtpClientSession<S, B, E>
+where
+    S: Read + Write,
+    B: AsMut<[u8]>,
 {
     fn drop(&mut self) {
         let _ = Quit.execute(&mut self.stream);
@@ -309,20 +863,28 @@ where
 }
 
 #[derive(Debug)]
-pub enum SendError<E: TcpError> {
+pub enum SendError<E: Debug> {
     IoError(E),
     NoMem,
     SendFailed,
     UnexpectedResponse,
+    /// A header field or address of the mail was rejected before being sent.
+    InvalidInput(InputError),
+    /// The message source failed, and the message data was left unterminated so the message isn't
+    /// delivered truncated. The session can only be closed (see `Connection::is_poisoned`).
+    MessageFailed,
+    /// The session was left in the middle of the message data by an earlier failure (see
+    /// `Connection::is_poisoned`).
+    Poisoned,
 }
 
-impl<E: TcpError> From<E> for SendError<E> {
+impl<E: Debug> From<E> for SendError<E> {
     fn from(value: E) -> Self {
         Self::IoError(value)
     }
 }
 
-impl<E: TcpError> From<ResponseError<'_, E>> for SendError<E> {
+impl<E: Debug> From<ResponseError<'_, E>> for SendError<E> {
     fn from(value: ResponseError<'_, E>) -> Self {
         match value {
             ResponseError::ReplyCodeError(_) => Self::SendFailed,
@@ -332,3 +894,782 @@ impl<E: TcpError> From<ResponseError<'_, E>> for SendError<E> {
         }
     }
 }
+
+impl<E: Debug> From<CommandError<E>> for SendError<E> {
+    fn from(value: CommandError<E>) -> Self {
+        match value {
+            CommandError::IoError(e) => Self::IoError(e),
+            CommandError::NoMem => Self::NoMem,
+            CommandError::UnexpectedResponse => Self::UnexpectedResponse,
+            CommandError::Rejected(_) => Self::SendFailed,
+            CommandError::Poisoned => Self::Poisoned,
+        }
+    }
+}
+
+impl<E: Debug> From<DataError<E>> for SendError<E> {
+    fn from(value: DataError<E>) -> Self {
+        match value {
+            DataError::IoError(e) => Self::IoError(e),
+            DataError::MessageFailed => Self::MessageFailed,
+            DataError::InvalidInput(e) => Self::InvalidInput(e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use core::fmt::Write as _;
+
+    use super::*;
+    use crate::{
+        date::{Clock, DateTime},
+        io::mock::Replay,
+        message::{
+            test::CountingRng, Attachment, Body, ChunkFn, MessageIdGenerator, Multipart, Part,
+        },
+    };
+
+    /// Session over a `Replay` of `replies`, once greeted and past EHLO.
+    fn session(replies: &str) -> SmtpClientSession<Replay, [u8; 64]> {
+        let replies = format!("220 mx.example.com ESMTP\r\n250 mx.example.com\r\n{replies}");
+        SmtpClient::from_transport(Replay::new(&replies), [0; 64])
+            .connect()
+            .expect("connected")
+    }
+
+    /// End the session with QUIT, returning everything written, from EHLO on.
+    fn transcript<B: AsMut<[u8]>>(session: SmtpClientSession<Replay, B>) -> String {
+        String::from_utf8(session.quit().expect("quit").written).unwrap()
+    }
+
+    #[test]
+    fn session_over_transport() {
+        let replay = Replay::new(
+            "220 mx.example.com ESMTP\r\n\
+             250-mx.example.com\r\n\
+             250 8BITMIME\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n",
+        );
+        let mut buf = [0; 256];
+
+        let mut session = SmtpClient::from_transport(replay, &mut buf[..])
+            .with_client_id(ClientId::new("client.example.com"))
+            .connect()
+            .expect("connected");
+        assert_eq!(session.ehlo_info().domain(), "mx.example.com");
+        assert!(session.ehlo_info().supports("8BITMIME"));
+
+        let to = ["bob@example.com".into()];
+        let mail = Mail::new()
+            .from("alice@example.com")
+            .to(&to)
+            .subject("Hi")
+            .body("Hello\r\n.Bob");
+        session.send(mail).expect("sent");
+
+        let replay = session.quit().expect("quit");
+        assert!(replay.replies.is_empty());
+        assert_eq!(
+            String::from_utf8(replay.written).unwrap(),
+            "EHLO client.example.com\r\n\
+             MAIL FROM:<alice@example.com>\r\n\
+             RCPT TO:<bob@example.com>\r\n\
+             DATA\r\n\
+             From:<alice@example.com>\r\n\
+             To:<bob@example.com>\r\n\
+             Subject:Hi\r\n\
+             \r\n\
+             Hello\r\n\
+             ..Bob\r\n\
+             .\r\n\
+             QUIT\r\n"
+        );
+    }
+
+    #[test]
+    fn send_raw_chunks() {
+        // the session buffer is much smaller than the message
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n",
+        );
+
+        // e.g., log records read one at a time from flash
+        let mut records = (0..100).map(|i| i % 4);
+        let chunks = core::iter::from_fn(|| {
+            let record = records.next()?;
+            Some(Ok::<_, ()>(match record {
+                0 => "Subject: logs\r",
+                1 => "\n\r\n.",
+                2 => "log line\n",
+                _ => ".",
+            }))
+        });
+        let envelope = Envelope::new(Some("alice@example.com"), ["bob@example.com"]);
+        session
+            .send_raw(envelope, MessageChunks(chunks))
+            .expect("sent");
+
+        let written = transcript(session);
+        let data = written
+            .split_once("DATA\r\n")
+            .and_then(|(_, data)| data.strip_suffix(".\r\nQUIT\r\n"))
+            .unwrap();
+        assert_eq!(
+            data,
+            "Subject: logs\r\n\r\n..log line\r\n..".repeat(25) + "\r\n"
+        );
+    }
+
+    #[test]
+    fn send_raw_reader() {
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n",
+        );
+
+        // e.g., a file, read through a buffer much smaller than the message
+        let file = Replay::new("Subject: logs\n\n.log line\n.log line\n");
+        let mut chunk = [0; 5];
+        let envelope = Envelope::new(Some("alice@example.com"), ["bob@example.com"]);
+        session
+            .send_raw(envelope, MessageReader::new(file, &mut chunk))
+            .expect("sent");
+
+        let written = transcript(session);
+        assert!(written
+            .ends_with("DATA\r\nSubject: logs\r\n\r\n..log line\r\n..log line\r\n.\r\nQUIT\r\n"));
+    }
+
+    #[test]
+    fn failed_message_source() {
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n",
+        );
+
+        // e.g., flash failing halfway through the log
+        let chunks = [
+            Ok("Subject: logs\r\n\r\n"),
+            Err("read error"),
+            Ok("tail\r\n"),
+        ];
+        let envelope = Envelope::new(Some("alice@example.com"), ["bob@example.com"]);
+        let result = session.send_raw(envelope, MessageChunks(chunks.into_iter()));
+        assert!(matches!(result, Err(SendError::MessageFailed)));
+
+        // anything else would be taken as part of the message
+        let envelope = Envelope::new(Some("alice@example.com"), ["bob@example.com"]);
+        let result = session.send_raw(envelope, "Subject: other\r\n");
+        assert!(matches!(result, Err(SendError::Poisoned)));
+
+        // the message is never ended, not even by QUIT
+        let written = transcript(session);
+        assert!(written.ends_with("DATA\r\nSubject: logs\r\n\r\n"));
+    }
+
+    #[test]
+    fn formatted_body() {
+        const REPLIES: &str = "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n";
+        let to = ["bob@example.com".into()];
+        let temperature = 21.5;
+
+        let mut bodies = Vec::new();
+        for mail in [
+            Mail::new()
+                .to(&to)
+                .body_fmt(format_args!("T={}\n.{}\n", temperature, "end")),
+            Mail::new().to(&to).body_with(&|f| {
+                writeln!(f, "T={}", temperature)?;
+                f.write_str(".end")
+            }),
+        ] {
+            let mut session = session(REPLIES);
+            session.send(mail).expect("sent");
+
+            let written = transcript(session);
+            bodies.push(written.split_once("\r\n\r\n").unwrap().1.to_owned());
+        }
+
+        for body in bodies {
+            assert_eq!(body, "T=21.5\r\n..end\r\n.\r\nQUIT\r\n");
+        }
+    }
+
+    #[test]
+    fn full_headers() {
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n",
+        );
+
+        let to = ["bob@example.com".into()];
+        let mail = Mail::new()
+            .from("alice@example.com")
+            .to(&to)
+            .subject("Re: Hi")
+            .date("Fri, 16 Oct 2026 09:12:01 +0200")
+            .message_id("2@example.com")
+            .sender("bot@example.com")
+            .reply_to("alice+replies@example.com")
+            .in_reply_to("1@example.com")
+            .references(&["0@example.com", "1@example.com"])
+            .custom_headers(&[("X-Priority", "1"), ("X-Mailer", "mailr")])
+            .body("Hello");
+        session.send(mail).expect("sent");
+
+        let written = transcript(session);
+        assert_eq!(
+            written.split_once("DATA\r\n").unwrap().1,
+            "Date:Fri, 16 Oct 2026 09:12:01 +0200\r\n\
+             From:<alice@example.com>\r\n\
+             Sender:<bot@example.com>\r\n\
+             Reply-To:<alice+replies@example.com>\r\n\
+             To:<bob@example.com>\r\n\
+             Message-ID:<2@example.com>\r\n\
+             In-Reply-To:<1@example.com>\r\n\
+             References:<0@example.com> <1@example.com>\r\n\
+             Subject:Re: Hi\r\n\
+             X-Priority:1\r\n\
+             X-Mailer:mailr\r\n\
+             \r\n\
+             Hello\r\n\
+             .\r\n\
+             QUIT\r\n"
+        );
+    }
+
+    #[test]
+    fn address_lists() {
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n",
+        );
+
+        let to = [
+            Mailbox::with_name("Plant 3, Line A", "plant3@example.com"),
+            Mailbox::with_name("Ops", "ops@example.com"),
+        ];
+        let cc = ["log@example.com".into()];
+        let bcc = [
+            "audit@example.com".into(),
+            Mailbox::with_name("Boss", "boss@example.com"),
+        ];
+        let mail = Mail::new()
+            .from(Mailbox::with_name("Sensor 7 (hall)", "sensor7@example.com"))
+            .to(&to)
+            .cc(&cc)
+            .bcc(&bcc);
+        session.send(mail).expect("sent");
+        session
+            .send(Mail::new().from("sensor7@example.com").bcc(&bcc))
+            .expect("sent");
+
+        let written = transcript(session);
+        let mut messages = written.split("DATA\r\n").skip(1);
+        assert_eq!(
+            messages.next().unwrap(),
+            "From:\"Sensor 7 (hall)\" <sensor7@example.com>\r\n\
+             To:\"Plant 3, Line A\" <plant3@example.com>, Ops <ops@example.com>\r\n\
+             Cc:<log@example.com>\r\n\
+             .\r\n\
+             MAIL FROM:<sensor7@example.com>\r\n\
+             RCPT TO:<audit@example.com>\r\n\
+             RCPT TO:<boss@example.com>\r\n"
+        );
+        assert_eq!(
+            messages.next().unwrap(),
+            "From:<sensor7@example.com>\r\n\
+             To:undisclosed-recipients:;\r\n\
+             .\r\n\
+             QUIT\r\n"
+        );
+    }
+
+    #[test]
+    fn folded_headers() {
+        let replies = "250 OK\r\n".repeat(31) + "354 Go ahead\r\n250 OK\r\n";
+        let mut session = session(&replies);
+
+        let addrs: Vec<_> = (0..30)
+            .map(|i| format!("technician{i}@plant.example.com"))
+            .collect();
+        let to: Vec<_> = addrs.iter().map(|addr| Mailbox::new(addr)).collect();
+        let mail = Mail::new()
+            .to(&to)
+            .subject(
+                "Weekly maintenance report for all the production lines of plant 3 and plant 4",
+            )
+            .body("Hello");
+        session.send(mail).expect("sent");
+
+        let written = transcript(session);
+        let (headers, _) = written
+            .split_once("DATA\r\n")
+            .unwrap()
+            .1
+            .split_once("\r\n\r\n")
+            .unwrap();
+        assert!(headers.lines().all(|line| line.len() <= MAX_LINE_LEN));
+        assert!(headers.starts_with(
+            "To:<technician0@plant.example.com>, <technician1@plant.example.com>,\r\n \
+             <technician2@plant.example.com>, <technician3@plant.example.com>,\r\n"
+        ));
+        assert!(headers.ends_with(
+            "\r\nSubject:Weekly maintenance report for all the production lines of plant 3 and\r\n plant 4"
+        ));
+        assert_eq!(headers.replace("\r\n ", " ").lines().count(), 2);
+    }
+
+    #[test]
+    fn non_ascii_headers() {
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n",
+        );
+
+        let to = ["bob@example.com".into()];
+        let mail = Mail::new()
+            .from(Mailbox::with_name("Jürgen", "juergen@example.com"))
+            .to(&to)
+            .subject("温度警告")
+            .body("Hello");
+        session.send(mail).expect("sent");
+
+        let written = transcript(session);
+        assert!(written.contains(
+            "From:=?UTF-8?Q?J=C3=BCrgen?= <juergen@example.com>\r\n\
+             To:<bob@example.com>\r\n\
+             Subject:=?UTF-8?B?5rip5bqm6K2m5ZGK?=\r\n"
+        ));
+    }
+
+    #[test]
+    fn multipart_alternative() {
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n",
+        );
+
+        let t = 21.5;
+        let text = format_args!("T={}\r\n.\r\n", t);
+        let html = |f: &mut core::fmt::Formatter<'_>| write!(f, "<p>T=<b>{}</b></p>", t);
+        let parts = [Part::text(text).into(), Part::html(Body::Fn(&html)).into()];
+        let boundaries = core::cell::Cell::new(0);
+        let to = ["bob@example.com".into()];
+        let mail = Mail::new()
+            .to(&to)
+            .subject("Report")
+            .multipart(Multipart::alternative(&parts, &boundaries));
+        session.send(mail).expect("sent");
+        assert_eq!(boundaries.get(), 1);
+
+        let written = transcript(session);
+        assert!(written.contains(
+            "Subject:Report\r\n\
+             MIME-Version:1.0\r\n\
+             Content-Type:multipart/alternative;\r\n boundary=\"=_mailr_00000000\"\r\n\
+             \r\n\
+             --=_mailr_00000000\r\n\
+             Content-Type:text/plain; charset=utf-8\r\n\
+             \r\n\
+             T=21.5\r\n\
+             ..\r\n\
+             \r\n\
+             --=_mailr_00000000\r\n\
+             Content-Type:text/html; charset=utf-8\r\n\
+             \r\n\
+             <p>T=<b>21.5</b></p>\r\n\
+             --=_mailr_00000000--\r\n\
+             .\r\n"
+        ));
+    }
+
+    #[test]
+    fn attachment() {
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n",
+        );
+
+        // far bigger than the session buffer, read in chunks as if from flash
+        let log = |sink: &mut dyn FnMut(&[u8]) -> core::fmt::Result| {
+            for i in 0..1000 {
+                sink(format!("{i},21.5\r\n").as_bytes())?;
+            }
+            Ok(())
+        };
+        let parts = [
+            Part::text("Log attached").into(),
+            Attachment::new("log.csv", "text/csv", &log as &ChunkFn).into(),
+        ];
+        let to = ["bob@example.com".into()];
+        let mail = Mail::new()
+            .to(&to)
+            .multipart(Multipart::mixed(&parts, "=_mailr_00000000"));
+        session.send(mail).expect("sent");
+
+        let written = transcript(session);
+        let (_, content) = written.split_once("filename=\"log.csv\"\r\n\r\n").unwrap();
+        let (content, _) = content.split_once("\r\n--=_mailr_00000000--\r\n").unwrap();
+        assert!(content.split("\r\n").all(|line| line.len() <= 76));
+        assert!(content.starts_with("MCwyMS41DQoxLDIxLjUNCjIsMjEuNQ0K"));
+        assert_eq!(
+            content.replace("\r\n", "").len(),
+            9890_usize.div_ceil(3) * 4
+        );
+    }
+
+    #[test]
+    fn failed_attachment() {
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n",
+        );
+
+        // e.g., flash failing halfway through the log
+        let log = |sink: &mut dyn FnMut(&[u8]) -> core::fmt::Result| {
+            sink(b"0,21.5\r\n")?;
+            Err(core::fmt::Error)
+        };
+        let parts = [
+            Part::text("Log attached").into(),
+            Attachment::new("log.csv", "text/csv", &log as &ChunkFn).into(),
+        ];
+        let to = ["bob@example.com".into()];
+        let mail = Mail::new()
+            .to(&to)
+            .multipart(Multipart::mixed(&parts, "=_mailr_00000000"));
+        assert!(matches!(session.send(mail), Err(SendError::MessageFailed)));
+        assert!(session.is_poisoned());
+
+        // the mail is never ended, so it isn't delivered without the log
+        let written = transcript(session);
+        assert!(written.ends_with("filename=\"log.csv\"\r\n\r\n"));
+    }
+
+    #[test]
+    fn date_from_clock() {
+        struct Rtc(core::cell::Cell<i64>);
+
+        impl Clock for Rtc {
+            fn now(&self) -> DateTime {
+                let now = self.0.get();
+                self.0.set(now + 1);
+                DateTime::new(now, 120)
+            }
+        }
+
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n",
+        );
+
+        let rtc = Rtc(1_792_134_721.into());
+        let to = ["bob@example.com".into()];
+        let mail = Mail::new().to(&to).date(&rtc).body("Hello");
+        session.send(mail).expect("sent");
+        assert_eq!(rtc.0.get(), 1_792_134_722, "read when sent");
+
+        // rejected before anything is sent
+        let mail = Mail::new()
+            .to(&to)
+            .date(DateTime::new(1_792_134_721, 24 * 60))
+            .body("Hello");
+        assert!(matches!(
+            session.send(mail),
+            Err(SendError::InvalidInput(InputError::Date))
+        ));
+
+        let written = transcript(session);
+        assert!(written.contains("DATA\r\nDate:Fri, 16 Oct 2026 09:12:01 +0200\r\n"));
+        assert!(written.ends_with(".\r\nQUIT\r\n"));
+    }
+
+    #[test]
+    fn generated_message_id() {
+        struct Rtc;
+
+        impl Clock for Rtc {
+            fn now(&self) -> DateTime {
+                DateTime::utc(1_792_134_721)
+            }
+        }
+
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n\
+             250 OK\r\n\
+             221 Bye\r\n",
+        );
+
+        let message_ids =
+            MessageIdGenerator::new(CountingRng(0xfa), ClientId::new("client.example.com"), &Rtc);
+        let to = ["bob@example.com".into()];
+        let mail = || Mail::new().to(&to).body("Hello");
+
+        let first = session
+            .send(mail().message_id(&message_ids))
+            .expect("sent")
+            .expect("generated");
+        assert_eq!(
+            first.to_string(),
+            "<1792134721.0.fafbfcfdfeff0001@client.example.com>"
+        );
+        let second = session
+            .send(mail().message_id(&message_ids))
+            .expect("sent")
+            .expect("generated");
+        assert_eq!(
+            second.as_str(),
+            "1792134721.1.0203040506070809@client.example.com"
+        );
+
+        let mail = mail().message_id("explicit@client.example.com");
+        assert_eq!(session.send(mail).expect("sent"), None);
+
+        let written = transcript(session);
+        let ids: Vec<_> = written
+            .lines()
+            .filter_map(|line| line.strip_prefix("Message-ID:"))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                "<1792134721.0.fafbfcfdfeff0001@client.example.com>",
+                "<1792134721.1.0203040506070809@client.example.com>",
+                "<explicit@client.example.com>",
+            ]
+        );
+    }
+
+    #[test]
+    fn transaction() {
+        let mut session = session(
+            "250 OK\r\n\
+             550 No such user\r\n\
+             250 OK\r\n\
+             251 Will forward\r\n\
+             354 Go ahead\r\n\
+             250 Queued\r\n\
+             250 OK\r\n\
+             250 OK\r\n",
+        );
+
+        let transaction = session.transaction("alice@example.com").unwrap();
+        let FirstRecipient::Rejected(transaction, code) =
+            transaction.add_recipient("nobody@example.com").unwrap()
+        else {
+            panic!("recipient accepted");
+        };
+        assert_eq!(&code, b"550");
+        let FirstRecipient::Accepted(mut transaction, _) =
+            transaction.add_recipient("bob@example.com").unwrap()
+        else {
+            panic!("recipient rejected");
+        };
+        assert_eq!(
+            &transaction.add_recipient("carol@example.com").unwrap(),
+            b"251"
+        );
+
+        let mut data = transaction.data().unwrap();
+        data.write_str("Subject: Hi\r").unwrap();
+        Write::write_all(&mut data, b"\n\r\n.Hello\n").unwrap();
+        write!(data, "{}", "x".repeat(40)).unwrap();
+        assert_eq!(&data.finish().unwrap(), b"250");
+
+        // dropped before DATA
+        let transaction = session.transaction(None).unwrap();
+        drop(transaction);
+
+        let written = transcript(session);
+        assert_eq!(
+            written,
+            "EHLO localhost\r\n\
+             MAIL FROM:<alice@example.com>\r\n\
+             RCPT TO:<nobody@example.com>\r\n\
+             RCPT TO:<bob@example.com>\r\n\
+             RCPT TO:<carol@example.com>\r\n\
+             DATA\r\n\
+             Subject: Hi\r\n\
+             \r\n\
+             ..Hello\r\n"
+                .to_owned()
+                + &"x".repeat(40)
+                + "\r\n\
+                   .\r\n\
+                   MAIL FROM:<>\r\n\
+                   RSET\r\n\
+                   QUIT\r\n"
+        );
+    }
+
+    #[test]
+    fn transaction_data_rejected() {
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             554 No valid recipients\r\n\
+             250 OK\r\n\
+             250 OK\r\n",
+        );
+
+        let transaction = session.transaction("alice@example.com").unwrap();
+        let Ok(FirstRecipient::Accepted(transaction, _)) =
+            transaction.add_recipient("bob@example.com")
+        else {
+            panic!("recipient rejected");
+        };
+        assert!(matches!(transaction.data(), Err(SendError::SendFailed)));
+
+        // the transaction was aborted, so the session can go on
+        assert!(!session.is_poisoned());
+        session.transaction(None).unwrap();
+
+        let written = transcript(session);
+        assert!(written.ends_with(
+            "DATA\r\n\
+             RSET\r\n\
+             MAIL FROM:<>\r\n\
+             RSET\r\n\
+             QUIT\r\n"
+        ));
+    }
+
+    #[test]
+    fn data_writer_dropped() {
+        let mut session = session(
+            "250 OK\r\n\
+             250 OK\r\n\
+             354 Go ahead\r\n",
+        );
+
+        let transaction = session.transaction("alice@example.com").unwrap();
+        let Ok(FirstRecipient::Accepted(transaction, _)) =
+            transaction.add_recipient("bob@example.com")
+        else {
+            panic!("recipient rejected");
+        };
+        let mut data = transaction.data().unwrap();
+        data.write_str("Subject: Hi\r\n\r\nHello").unwrap();
+        Write::flush(&mut data).unwrap();
+        drop(data);
+
+        // nothing is sent in the middle of the message anymore
+        assert!(session.is_poisoned());
+        assert!(matches!(
+            session.transaction(None),
+            Err(SendError::Poisoned)
+        ));
+        assert!(matches!(session.execute(Rset), Err(CommandError::Poisoned)));
+
+        let written = transcript(session);
+        assert!(written.ends_with("DATA\r\nSubject: Hi\r\n\r\nHello"));
+    }
+
+    #[test]
+    fn invalid_raw_mail() {
+        let mut session = session("");
+
+        let to = ["bob@example.com".into()];
+        let mail = Mail::new()
+            .from("alice@example.com")
+            .to(&to)
+            .subject("Hi\r\nBcc: eve@example.com");
+        let envelope = Envelope::new("alice@example.com", ["eve@example.com"]);
+        assert!(matches!(
+            session.send_raw(envelope, mail),
+            Err(SendError::InvalidInput(InputError::HeaderValue))
+        ));
+        assert!(!session.is_poisoned());
+
+        assert_eq!(transcript(session), "EHLO localhost\r\nQUIT\r\n");
+    }
+
+    #[test]
+    fn invalid_input() {
+        let mut session = session("");
+
+        let to = ["bob@example.com".into()];
+        let mail = Mail::new()
+            .from("alice@example.com")
+            .to(&to)
+            .subject("Hi\r\nBcc: eve@example.com");
+        assert!(matches!(
+            session.send(mail),
+            Err(SendError::InvalidInput(InputError::HeaderValue))
+        ));
+
+        let bcc = ["eve@example.com>\r\nDATA".into()];
+        let mail = Mail::new().from("alice@example.com").to(&to).bcc(&bcc);
+        assert!(matches!(
+            session.send(mail),
+            Err(SendError::InvalidInput(InputError::Address))
+        ));
+
+        // nothing was sent for either mail
+        assert_eq!(transcript(session), "EHLO localhost\r\nQUIT\r\n");
+
+        let replay = Replay::new("220 mx.example.com ESMTP\r\n");
+        let result = SmtpClient::from_transport(replay, [0; 64])
+            .with_client_id(ClientId::new("client.example.com\r\nMAIL FROM:<>"))
+            .connect();
+        assert!(matches!(
+            result,
+            Err(ConnectError::InvalidInput(InputError::ClientId))
+        ));
+    }
+
+    #[test]
+    fn greeting_rejected_over_transport() {
+        let replay = Replay::new("554 No SMTP service here\r\n");
+        let mut buf = [0; 256];
+
+        let result = SmtpClient::from_transport(replay, &mut buf[..]).connect();
+        assert!(matches!(result, Err(ConnectError::GreetingRejected(code)) if &code == b"554"));
+    }
+}
@@ -0,0 +1,241 @@
+use core::fmt::Debug;
+
+use embedded_nal::{Dns, TcpClientStack, TcpError, UdpClientStack};
+use heapless::Vec;
+
+use super::{
+    commands::{prepare_mail, MailData},
+    send_transaction, ClientId, ConnectAnyError, ConnectHostnameError, DataMessage, DeliveryStatus,
+    Endpoint, SendError, SmtpClient,
+};
+use crate::{
+    dns::{MxRecord, MxResolver, ResolveError},
+    message::{Mail, Mailbox, MessageIdBuf},
+};
+
+/// Maximum number of mail exchangers tried per recipient domain.
+pub const MAX_EXCHANGERS: usize = 4;
+
+type DeliveryResult<T, U, D> = Result<
+    (),
+    MxDeliveryError<<U as UdpClientStack>::Error, <D as Dns>::Error, <T as TcpClientStack>::Error>,
+>;
+
+/// Delivers mail directly to the mail exchangers of each recipient domain, without going through a
+/// smarthost.
+pub struct MxDelivery<'a, T, U, D>
+where
+    T: TcpClientStack,
+    U: UdpClientStack,
+    D: Dns,
+{
+    stack: &'a mut T,
+    buffer: &'a mut [u8],
+    resolver: MxResolver<'a, U>,
+    dns: &'a mut D,
+    client_id: Option<ClientId<'a>>,
+    port: u16,
+}
+
+impl<'a, T, U, D> MxDelivery<'a, T, U, D>
+where
+    T: TcpClientStack,
+    U: UdpClientStack,
+    D: Dns,
+{
+    /// `buffer` is used for both the DNS messages and the SMTP sessions, and should be at least
+    /// `dns::MAX_UDP_MESSAGE_LEN` long. `dns` resolves the exchangers' hostnames to addresses.
+    pub fn new(
+        stack: &'a mut T,
+        buffer: &'a mut [u8],
+        resolver: MxResolver<'a, U>,
+        dns: &'a mut D,
+    ) -> Self {
+        Self {
+            stack,
+            buffer,
+            resolver,
+            dns,
+            client_id: None,
+            port: 25,
+        }
+    }
+
+    /// Should be the client's fully-qualified domain name, as most exchangers reject `localhost`.
+    pub fn with_client_id(mut self, value: impl Into<Option<ClientId<'a>>>) -> Self {
+        self.client_id = value.into();
+        self
+    }
+
+    /// Port to connect to on the exchangers. Defaults to 25.
+    pub fn with_port(mut self, value: u16) -> Self {
+        self.port = value;
+        self
+    }
+
+    /// Send the mail to every recipient, grouping recipients by domain. For each domain, the
+    /// exchangers are tried in order of preference until one can be connected to (see
+    /// `SmtpClientConnector::connect_any`) and doesn't temporarily reject the mail (4xx reply to
+    /// MAIL FROM or RCPT TO).
+    ///
+    /// The result for each domain is reported through `on_result`, along with the domain (or the
+    /// recipient address, if it has no domain).
+    ///
+    /// Returns the Message-ID generated for the mail, if any, like `SmtpClientSession::send`. The
+    /// mail is checked beforehand, and nothing is sent if it's invalid.
+    // FIXME: Blocking for simplicity
+    pub fn send<'m, Mb, To, Cc, Bcc>(
+        &mut self,
+        mail: Mail<'m, Mb, To, Cc, Bcc>,
+        mut on_result: impl FnMut(&str, DeliveryResult<T, U, D>),
+    ) -> Result<Option<MessageIdBuf>, SendError<T::Error>>
+    where
+        Mb: AsRef<Mailbox<'m>> + Clone,
+        To: Iterator<Item = Mb> + Clone,
+        Cc: Iterator<Item = Mb> + Clone,
+        Bcc: Iterator<Item = Mb> + Clone,
+    {
+        let (message, bcc, message_id) = prepare_mail(mail)?;
+        let data = MailData {
+            mail: message.clone(),
+            message_id: message_id.as_ref().map(MessageIdBuf::as_str),
+            has_bcc: bcc.clone().next().is_some(),
+        };
+        let recipients = || {
+            message
+                .to
+                .clone()
+                .chain(message.cc.clone())
+                .chain(bcc.clone())
+                .map(|m| m.as_ref().address)
+        };
+
+        for (i, address) in recipients().enumerate() {
+            let Some(domain) = domain_of(address) else {
+                on_result(address, Err(MxDeliveryError::InvalidAddress));
+                continue;
+            };
+
+            let same_domain =
+                |addr: &str| domain_of(addr).is_some_and(|d| d.eq_ignore_ascii_case(domain));
+
+            // already delivered along with an earlier recipient
+            if recipients().take(i).any(same_domain) {
+                continue;
+            }
+
+            let sender = message.from.map(|m| m.address);
+            let receivers = || recipients().filter(|addr| same_domain(addr));
+
+            let result = self.send_to_domain(domain, sender, receivers, &data);
+            on_result(domain, result);
+        }
+
+        Ok(message_id)
+    }
+
+    /// Send the mail to the exchangers of `domain`, moving on to the next one if it can't be
+    /// connected to or temporarily rejects the mail.
+    fn send_to_domain<'m, I, M>(
+        &mut self,
+        domain: &str,
+        sender: Option<&str>,
+        receivers: impl Fn() -> I,
+        message: &M,
+    ) -> DeliveryResult<T, U, D>
+    where
+        I: Iterator<Item = &'m str>,
+        M: DataMessage + Clone,
+    {
+        let exchangers: Vec<MxRecord, MAX_EXCHANGERS> = self
+            .resolver
+            .resolve(domain, self.buffer)
+            .map_err(MxDeliveryError::ResolveError)?;
+
+        if let [MxRecord { exchange, .. }] = &exchangers[..] {
+            if exchange.is_empty() {
+                return Err(MxDeliveryError::NullMx);
+            }
+        }
+
+        // without MX records, the domain itself is the exchanger
+        let implicit = exchangers.is_empty().then_some(domain);
+        let port = self.port;
+        let endpoints = exchangers
+            .iter()
+            .map(|mx| mx.exchange.as_str())
+            .chain(implicit)
+            .map(|host| Endpoint::Hostname(host, port));
+
+        let mut next = 0;
+        let mut deferred = None;
+
+        loop {
+            let mut last_error = None;
+
+            let (mut session, index) = SmtpClient::new(&mut *self.stack, &mut *self.buffer)
+                .with_client_id(self.client_id)
+                .connect_any(self.dns, endpoints.clone().skip(next), |_, e| {
+                    last_error = Some(e)
+                })
+                .map_err(|e| match (e, deferred, last_error) {
+                    // an exchanger already took the mail, only not right now
+                    (_, Some(code), _) => MxDeliveryError::Deferred(code),
+                    (ConnectAnyError::AllFailed, None, Some(error))
+                    | (ConnectAnyError::Aborted { error, .. }, None, _) => {
+                        MxDeliveryError::ConnectError(error)
+                    }
+                    (ConnectAnyError::AllFailed, None, None) => MxDeliveryError::NoExchanger,
+                })?;
+            next += index + 1;
+
+            // a 4xx reply to MAIL FROM or RCPT TO means the next exchanger should be tried
+            // (https://www.rfc-editor.org/rfc/rfc5321#section-5.1)
+            let mut delivered = true;
+            let result = send_transaction(
+                &mut session.stream,
+                session.protocol,
+                sender,
+                receivers(),
+                message.clone(),
+                true,
+                |_, status| delivered &= status == DeliveryStatus::Delivered,
+            );
+            match result {
+                Ok(Some(code)) => deferred = Some(code),
+                Ok(None) if delivered => return Ok(()),
+                Ok(None) => return Err(MxDeliveryError::SendError(SendError::SendFailed)),
+                Err(e) => return Err(MxDeliveryError::SendError(e)),
+            }
+        }
+    }
+}
+
+fn domain_of(address: &str) -> Option<&str> {
+    address
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .filter(|domain| !domain.is_empty())
+}
+
+#[derive(Debug)]
+pub enum MxDeliveryError<UE, DE, E>
+where
+    UE: Debug,
+    DE: Debug,
+    E: TcpError,
+{
+    /// The recipient address has no domain.
+    InvalidAddress,
+    ResolveError(ResolveError<UE>),
+    /// The domain explicitly accepts no mail (https://www.rfc-editor.org/rfc/rfc7505).
+    NullMx,
+    /// None of the exchangers could be connected to. Holds the error of the last one tried.
+    ConnectError(ConnectHostnameError<DE, E>),
+    /// There was no exchanger to try.
+    NoExchanger,
+    /// Every exchanger temporarily rejected the mail, so it should be sent again later. Holds the
+    /// last reply code.
+    Deferred([u8; 3]),
+    SendError(SendError<E>),
+}
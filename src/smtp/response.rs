@@ -23,14 +23,19 @@ where
                     // thinks we've borrowed self for this whole block. But if it's not an error, we should
                     // be safe to borrow the value again since self's lifetime hasn't left the block.
                     // The function signature's explicit lifetime ensure this is safe.
-                    return Err(unsafe { core::mem::transmute(e) });
+                    return Err(unsafe {
+                        core::mem::transmute::<
+                            ResponseError<'_, R::Error>,
+                            ResponseError<'a, R::Error>,
+                        >(e)
+                    });
                 }
             };
 
             if line.code != code {
                 return Err(ResponseError::ReplyCodeError(
                     // SAFETY: same as above, transmute to cast the lifetime away.
-                    unsafe { core::mem::transmute(line.code) },
+                    unsafe { core::mem::transmute::<&[u8], &'a [u8]>(line.code) },
                 ));
             }
 
@@ -41,8 +46,29 @@ where
         Ok(())
     }
 
+    /// Read a whole (possibly multiline) reply and return its reply code.
+    pub fn next_reply(&mut self) -> Result<[u8; 3], ResponseError<'static, R::Error>> {
+        loop {
+            let line = self.next_line().map_err(|e| match e {
+                ResponseError::ReadError(e) => ResponseError::ReadError(e),
+                ResponseError::NoMem => ResponseError::NoMem,
+                ResponseError::ReplyCodeError(_) | ResponseError::FormatError => {
+                    ResponseError::FormatError
+                }
+            })?;
+
+            if !line.has_next {
+                let code = line
+                    .code
+                    .try_into()
+                    .map_err(|_| ResponseError::FormatError)?;
+                return Ok(code);
+            }
+        }
+    }
+
     /// Return the next reply line and whether the reply continues (expecting another line)
-    pub fn next_line(&mut self) -> Result<ReplyLine, ResponseError<R::Error>> {
+    pub fn next_line(&mut self) -> Result<ReplyLine<'_>, ResponseError<'_, R::Error>> {
         let line = self.0.read_line()?.as_bytes();
 
         let (code, text) = line.split_at_checked(3).ok_or(ResponseError::FormatError)?;
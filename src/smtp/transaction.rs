@@ -0,0 +1,279 @@
+use core::{fmt, marker::PhantomData, mem::ManuallyDrop, ops::Range};
+
+use embedded_nal::nb;
+
+use super::{
+    commands::{read_data_replies, Command, Connection, DeliveryStatus, MailFrom, Rset},
+    dot_stuff::{DotStuffState, DotStuffWriter},
+    response::ResponseParser,
+    Protocol, SendError,
+};
+use crate::{
+    io::{self, ErrorType, Read},
+    message::check_address,
+};
+
+/// State of a `Transaction` without any recipient accepted yet.
+pub struct NoRecipients;
+
+/// State of a `Transaction` with at least one recipient accepted, ready for the message data.
+pub struct Recipients;
+
+/// A mail transaction (https://www.rfc-editor.org/rfc/rfc5321#section-3.3) started with
+/// `SmtpClientSession::transaction`, for sending a message step by step.
+///
+/// Dropping it before the message data is sent aborts it with RSET.
+pub struct Transaction<'s, S, B, State>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    stream: &'s mut Connection<S, B>,
+    protocol: Protocol,
+    /// Number of recipients accepted so far
+    accepted: usize,
+    state: PhantomData<State>,
+}
+
+/// Reply to the first recipients of a `Transaction`, moving it to the `Recipients` state once one
+/// is accepted.
+pub enum FirstRecipient<'s, S, B>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    Accepted(Transaction<'s, S, B, Recipients>, [u8; 3]),
+    Rejected(Transaction<'s, S, B, NoRecipients>, [u8; 3]),
+}
+
+impl<'s, S, B> Transaction<'s, S, B, NoRecipients>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    pub(crate) fn start(
+        stream: &'s mut Connection<S, B>,
+        protocol: Protocol,
+        sender_addr: Option<&str>,
+    ) -> Result<Self, SendError<S::Error>> {
+        MailFrom(sender_addr).execute(&mut *stream)?;
+
+        Ok(Self {
+            stream,
+            protocol,
+            accepted: 0,
+            state: PhantomData,
+        })
+    }
+
+    /// Add a recipient with RCPT TO.
+    pub fn add_recipient(
+        mut self,
+        addr: &str,
+    ) -> Result<FirstRecipient<'s, S, B>, SendError<S::Error>> {
+        Ok(match self.rcpt_to(addr)? {
+            code @ [b'2', ..] => FirstRecipient::Accepted(self.into_state(), code),
+            code => FirstRecipient::Rejected(self, code),
+        })
+    }
+}
+
+impl<'s, S, B> Transaction<'s, S, B, Recipients>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    /// Add another recipient with RCPT TO, returning the reply code (2xx if accepted).
+    pub fn add_recipient(&mut self, addr: &str) -> Result<[u8; 3], SendError<S::Error>> {
+        self.rcpt_to(addr)
+    }
+
+    /// Start sending the message data with DATA. If the server doesn't go ahead, the transaction
+    /// is aborted with RSET.
+    pub fn data(self) -> Result<DataWriter<'s, S, B>, SendError<S::Error>> {
+        // until the server goes ahead, dropping `self` on failure sends RSET
+        self.stream.write_command(format_args!("DATA"))?;
+        ResponseParser::new(&mut *self.stream).expect_code(b"354")?;
+
+        let me = ManuallyDrop::new(self);
+
+        // SAFETY: `me` is never used nor dropped after reading from it.
+        let stream = unsafe { core::ptr::read(&me.stream) };
+
+        Ok(DataWriter {
+            stream,
+            protocol: me.protocol,
+            recipients: me.accepted,
+            pending: 0..0,
+            state: DotStuffState::default(),
+        })
+    }
+}
+
+impl<'s, S, B, State> Transaction<'s, S, B, State>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    fn rcpt_to(&mut self, addr: &str) -> Result<[u8; 3], SendError<S::Error>> {
+        check_address(addr).map_err(SendError::InvalidInput)?;
+        self.stream
+            .write_command(format_args!("RCPT TO:<{}>", addr))?;
+        let code = ResponseParser::new(&mut *self.stream).next_reply()?;
+
+        if code[0] == b'2' {
+            self.accepted += 1;
+        }
+        Ok(code)
+    }
+
+    fn into_state<T>(self) -> Transaction<'s, S, B, T> {
+        let me = ManuallyDrop::new(self);
+
+        Transaction {
+            // SAFETY: `me` is never used nor dropped after reading from it.
+            stream: unsafe { core::ptr::read(&me.stream) },
+            protocol: me.protocol,
+            accepted: me.accepted,
+            state: PhantomData,
+        }
+    }
+}
+
+impl<S, B, State> Drop for Transaction<'_, S, B, State>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    fn drop(&mut self) {
+        let _ = Rset.execute(self.stream);
+    }
+}
+
+/// Writer of the message data of a `Transaction`, escaping lines beginning with a period and
+/// normalizing line endings like `DotStuffWriter`.
+///
+/// `finish` must be called to end the message. If dropped before, the message is left
+/// unterminated so it isn't delivered truncated, and the session can only be closed (see
+/// `Connection::is_poisoned`).
+pub struct DataWriter<'s, S, B>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    stream: &'s mut Connection<S, B>,
+    protocol: Protocol,
+    recipients: usize,
+    /// Part of the write buffer not written to `stream` yet
+    pending: Range<usize>,
+    state: DotStuffState,
+}
+
+impl<S, B> DataWriter<'_, S, B>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    /// Run `f` with a `DotStuffWriter` resuming where the previous one stopped.
+    fn with_writer<T>(
+        &mut self,
+        f: impl FnOnce(&mut DotStuffWriter<'_, '_, S>) -> Result<T, S::Error>,
+    ) -> Result<T, S::Error> {
+        let mut writer = self.stream.stream.writer_with_pending(self.pending.clone());
+        let mut stuffer = DotStuffWriter::resume(&mut writer, self.state);
+        let result = f(&mut stuffer);
+        self.state = stuffer.state();
+        self.pending = writer.into_pending();
+        result
+    }
+
+    /// End the message data, passing `on_reply` the index of each accepted recipient and its
+    /// reply code.
+    fn end(self, on_reply: impl FnMut(usize, [u8; 3])) -> Result<(), SendError<S::Error>> {
+        let me = ManuallyDrop::new(self);
+
+        // SAFETY: `me` is never used nor dropped after reading from it.
+        let stream = unsafe { core::ptr::read(&me.stream) };
+
+        let mut writer = stream.stream.writer_with_pending(me.pending.clone());
+        let ended = DotStuffWriter::resume(&mut writer, me.state)
+            .finish()
+            .and_then(|()| writer.flush());
+        drop(writer);
+        if let Err(e) = ended {
+            stream.poison();
+            return Err(e.into());
+        }
+
+        read_data_replies(stream, me.protocol, me.recipients, on_reply)
+    }
+
+    /// End the message data, returning the reply code (with LMTP, the first failure reported
+    /// among the accepted recipients, if any).
+    pub fn finish(self) -> Result<[u8; 3], SendError<S::Error>> {
+        let mut reply = None::<[u8; 3]>;
+        self.end(|_, code| {
+            if reply.is_none_or(|reply| reply[0] == b'2') {
+                reply = Some(code);
+            }
+        })?;
+
+        reply.ok_or(SendError::UnexpectedResponse)
+    }
+
+    /// End the message data, reporting the delivery status of each accepted recipient through
+    /// `on_status`, in the order they were added.
+    pub fn finish_with_status(
+        self,
+        mut on_status: impl FnMut(usize, DeliveryStatus),
+    ) -> Result<(), SendError<S::Error>> {
+        self.end(|i, code| on_status(i, DeliveryStatus::from_reply(code)))
+    }
+}
+
+impl<S, B> Drop for DataWriter<'_, S, B>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    fn drop(&mut self) {
+        self.stream.poison();
+    }
+}
+
+impl<S, B> ErrorType for DataWriter<'_, S, B>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    type Error = S::Error;
+}
+
+impl<S, B> io::Write for DataWriter<'_, S, B>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    // FIXME: Blocking for now for simplicity
+    fn write(&mut self, buffer: &[u8]) -> nb::Result<usize, Self::Error> {
+        self.with_writer(|w| w.write(buffer))?;
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Self::Error> {
+        let mut writer = self.stream.stream.writer_with_pending(self.pending.clone());
+        let result = writer.flush();
+        self.pending = writer.into_pending();
+        result
+    }
+}
+
+impl<S, B> fmt::Write for DataWriter<'_, S, B>
+where
+    S: Read + io::Write,
+    B: AsMut<[u8]>,
+{
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.with_writer(|w| w.write_str(s)).map_err(|_| fmt::Error)
+    }
+}
@@ -3,6 +3,7 @@ use std::env::{self, VarError};
 pub struct TestContext {
     pub plain_port: u16,
     pub tls_port: u16,
+    pub lmtp_port: u16,
     pub tls_cert: Option<String>,
     pub username: String,
     pub password: String,
@@ -14,6 +15,7 @@ impl TestContext {
         const AUTH_PASS_ENV: &str = "AUTH_PASS";
         const PLAIN_PORT_ENV: &str = "PLAIN_PORT";
         const TLS_PORT_ENV: &str = "TLS_PORT";
+        const LMTP_PORT_ENV: &str = "LMTP_PORT";
         const TLS_CERT_PORT_ENV: &str = "TLS_CERT";
 
         let user = env::var(AUTH_USER_ENV);
@@ -46,6 +48,14 @@ impl TestContext {
             invalid => panic!("{:?}", invalid),
         };
 
+        let lmtp_port = match env::var(LMTP_PORT_ENV) {
+            Err(VarError::NotPresent) => 2424,
+            Ok(port) => port
+                .parse()
+                .unwrap_or_else(|_| panic!("{} must be a u16. Got: {}", LMTP_PORT_ENV, port)),
+            invalid => panic!("{:?}", invalid),
+        };
+
         let tls_cert = match env::var(TLS_CERT_PORT_ENV) {
             Err(VarError::NotPresent) => Some("tests/data/cert.pem".into()),
             Ok(path) => {
@@ -61,6 +71,7 @@ impl TestContext {
         Self {
             plain_port,
             tls_port,
+            lmtp_port,
             tls_cert,
             username,
             password,
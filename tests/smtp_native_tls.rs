@@ -214,9 +214,73 @@ mod connect {
         let mut stack = tls::TlsStack::new(tls_cert);
         let mut buf = [0; 1024];
 
-        let _client = SmtpClient::new(&mut stack, &mut buf[..])
+        let client = SmtpClient::new(&mut stack, &mut buf[..])
             .with_auth(Some(Credential::new(&username, &password)))
             .connect(([127, 0, 0, 1], tls_port))
             .expect("should authenticate successfully");
+
+        let mut mechanisms: Vec<_> = client.ehlo_info().auth_mechanisms().collect();
+        mechanisms.sort();
+        assert_eq!(mechanisms, ["LOGIN", "PLAIN"]);
+    }
+}
+
+#[cfg(feature = "embedded-io")]
+mod embedded_io_transport {
+    use mailr_nal::{auth::Credential, io::FromEmbeddedIo, smtp::SmtpClient};
+    use native_tls::{Certificate, TlsConnector};
+    use std::{fs, io, net::TcpStream};
+    use test_common::TestContext;
+
+    /// `embedded_io` implementation for `std` streams.
+    struct StdIo<T>(T);
+
+    impl<T> embedded_io::ErrorType for StdIo<T> {
+        type Error = io::Error;
+    }
+
+    impl<T: io::Read> embedded_io::Read for StdIo<T> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            self.0.read(buf)
+        }
+    }
+
+    impl<T: io::Write> embedded_io::Write for StdIo<T> {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            self.0.write(buf)
+        }
+
+        fn flush(&mut self) -> Result<(), Self::Error> {
+            self.0.flush()
+        }
+    }
+
+    #[test]
+    fn tls_transport() {
+        let TestContext {
+            tls_port,
+            tls_cert,
+            username,
+            password,
+            ..
+        } = TestContext::setup();
+
+        let mut tls = TlsConnector::builder();
+        if let Some(path) = tls_cert {
+            tls.add_root_certificate(Certificate::from_pem(&fs::read(path).unwrap()).unwrap());
+        }
+        let tls = tls.build().unwrap();
+
+        let stream = TcpStream::connect(("127.0.0.1", tls_port)).unwrap();
+        let stream = tls.connect("localhost", stream).unwrap();
+        let mut buf = [0; 1024];
+
+        let client = SmtpClient::from_transport(FromEmbeddedIo(StdIo(stream)), &mut buf[..])
+            .with_auth(Some(Credential::new(&username, &password)))
+            .connect()
+            .expect("should authenticate successfully");
+
+        let mut stream = client.quit().expect("quit").into_inner().0;
+        stream.shutdown().unwrap();
     }
 }
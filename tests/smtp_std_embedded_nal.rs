@@ -2,8 +2,11 @@
 mod connect {
     use mailr_nal::{
         auth::Credential,
-        smtp::{ClientId, ConnectError, SmtpClient},
+        smtp::{
+            ClientId, ConnectAnyError, ConnectError, ConnectHostnameError, Endpoint, SmtpClient,
+        },
     };
+    use std::{io::Write, net::TcpListener, thread};
     use test_common::TestContext;
 
     #[test]
@@ -18,6 +21,30 @@ mod connect {
             .expect("connected without authentication");
     }
 
+    #[test]
+    fn ehlo_info() {
+        let TestContext { plain_port, .. } = TestContext::setup();
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let client = SmtpClient::new(&mut stack, &mut buf[..])
+            .with_ehlo_capacity::<512>()
+            .connect(([127, 0, 0, 1], plain_port))
+            .expect("connected");
+
+        let info = client.ehlo_info();
+        assert!(!info.domain().is_empty());
+        assert!(!info.is_truncated());
+        assert!(info.supports("8BITMIME"));
+        assert!(info.supports("smtputf8"));
+        assert!(
+            !info.supports("AUTH"),
+            "AUTH requires TLS on the test server"
+        );
+        assert_eq!(info.max_size(), Some(33554432));
+    }
+
     #[test]
     fn with_client_id() {
         let TestContext { plain_port, .. } = TestContext::setup();
@@ -57,6 +84,130 @@ mod connect {
         );
     }
 
+    /// Server that greets every connection with `greeting`, then hangs up. Returns its port.
+    fn spawn_greeter(greeting: &'static str) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let _ = stream.write_all(greeting.as_bytes());
+            }
+        });
+
+        port
+    }
+
+    /// Port with nothing listening on it.
+    fn closed_port() -> u16 {
+        TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port()
+    }
+
+    #[test]
+    fn failover() {
+        let TestContext { plain_port, .. } = TestContext::setup();
+        let busy_port = spawn_greeter("421 4.3.2 Service not available\r\n");
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut dns = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let endpoints = [
+            Endpoint::Addr(([127, 0, 0, 1], closed_port()).into()),
+            Endpoint::Addr(([127, 0, 0, 1], busy_port).into()),
+            ("unreachable.invalid", plain_port).into(),
+            ("localhost", plain_port).into(),
+        ];
+
+        let mut failures = Vec::new();
+        let (_client, used) = SmtpClient::new(&mut stack, &mut buf[..])
+            .connect_any(&mut dns, endpoints, |i, e| failures.push((i, e)))
+            .expect("connected to the last endpoint");
+
+        assert_eq!(used, 3);
+        assert!(
+            matches!(
+                &failures[..],
+                [
+                    (
+                        0,
+                        ConnectHostnameError::ConnectError(ConnectError::IoError(_))
+                    ),
+                    (
+                        1,
+                        ConnectHostnameError::ConnectError(ConnectError::GreetingRejected([
+                            b'4', b'2', b'1'
+                        ]))
+                    ),
+                    (2, ConnectHostnameError::DnsError(_)),
+                ]
+            ),
+            "Got: {:?}",
+            failures,
+        );
+    }
+
+    #[test]
+    fn failover_aborts_on_permanent_rejection() {
+        let TestContext { plain_port, .. } = TestContext::setup();
+        let rejecting_port = spawn_greeter("554 5.7.1 No SMTP service here\r\n");
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut dns = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let endpoints = [
+            Endpoint::Addr(([127, 0, 0, 1], rejecting_port).into()),
+            Endpoint::Addr(([127, 0, 0, 1], plain_port).into()),
+        ];
+
+        let result =
+            SmtpClient::new(&mut stack, &mut buf[..])
+                .connect_any(&mut dns, endpoints, |_, _| panic!("no failover expected"));
+
+        assert!(
+            matches!(
+                result,
+                Err(ConnectAnyError::Aborted {
+                    index: 0,
+                    error: ConnectHostnameError::ConnectError(ConnectError::GreetingRejected([
+                        b'5', b'5', b'4'
+                    ])),
+                })
+            ),
+            "Got: {:?}",
+            result,
+        );
+    }
+
+    #[test]
+    fn failover_all_failed() {
+        let mut stack = std_embedded_nal::Stack;
+        let mut dns = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let endpoints = [
+            Endpoint::Addr(([127, 0, 0, 1], closed_port()).into()),
+            Endpoint::Addr(([127, 0, 0, 1], closed_port()).into()),
+        ];
+
+        let mut failed = Vec::new();
+        let result =
+            SmtpClient::new(&mut stack, &mut buf[..])
+                .connect_any(&mut dns, endpoints, |i, _| failed.push(i));
+
+        assert!(
+            matches!(result, Err(ConnectAnyError::AllFailed)),
+            "Got: {:?}",
+            result
+        );
+        assert_eq!(failed, [0, 1]);
+    }
+
     #[test]
     fn hostname_dns() {
         let TestContext { plain_port, .. } = TestContext::setup();
@@ -90,6 +241,13 @@ mod send {
             .bcc(&[Mailbox::new("Brown@foo.com")])
             .subject(None)
             .subject("Test mail")
+            .date("Fri, 16 Oct 2026 09:12:01 +0200")
+            .message_id("1234@bar.com")
+            .sender("Secretary@bar.com")
+            .reply_to(Mailbox::with_name("Smith", "Smith@bar.com"))
+            .in_reply_to("1233@foo.com")
+            .references(&["1232@bar.com", "1233@foo.com"])
+            .custom_headers(&[("X-Priority", "1")])
             .body(None)
             .body("Blah blah blah...\r\n..etc. etc. etc.");
     }
@@ -196,3 +354,725 @@ mod send {
         client.close().expect("close successfully");
     }
 }
+
+#[cfg(test)]
+mod transaction {
+    use mailr_nal::{
+        io::Write as _,
+        message::Mail,
+        smtp::{FirstRecipient, SmtpClient},
+    };
+    use std::fmt::Write as _;
+    use test_common::TestContext;
+
+    #[test]
+    fn send_step_by_step() {
+        let TestContext { plain_port, .. } = TestContext::setup();
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let mut client = SmtpClient::new(&mut stack, &mut buf[..])
+            .connect(([127, 0, 0, 1], plain_port))
+            .unwrap();
+
+        let transaction = client.transaction("Smith@bar.com").expect("MAIL accepted");
+        let FirstRecipient::Accepted(mut transaction, code) = transaction
+            .add_recipient("Jones@foo.com")
+            .expect("RCPT replied")
+        else {
+            panic!("recipient rejected");
+        };
+        assert_eq!(&code, b"250");
+        assert_eq!(&transaction.add_recipient("Green@foo.com").unwrap(), b"250");
+
+        let mut data = transaction.data().expect("DATA accepted");
+        write!(data, "Subject: Step by step\r\n\r\n").unwrap();
+        for i in 0..100 {
+            writeln!(data, ".line {}", i).unwrap();
+        }
+        data.write_all(b"Blah blah blah...").unwrap();
+        assert_eq!(&data.finish().expect("message sent"), b"250");
+
+        // the session can still be used afterwards
+        let to = ["Jones@foo.com".into()];
+        let mail = Mail::new().from("Smith@bar.com").to(&to).body("Blah");
+        client.send(mail).expect("sent another message");
+    }
+
+    #[test]
+    fn reset_on_drop() {
+        let TestContext { plain_port, .. } = TestContext::setup();
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let mut client = SmtpClient::new(&mut stack, &mut buf[..])
+            .connect(([127, 0, 0, 1], plain_port))
+            .unwrap();
+
+        let transaction = client.transaction("Smith@bar.com").expect("MAIL accepted");
+        let Ok(FirstRecipient::Accepted(..)) = transaction.add_recipient("Jones@foo.com") else {
+            panic!("recipient rejected");
+        };
+
+        // a new transaction would be refused without RSET
+        let to = ["Green@foo.com".into()];
+        let mail = Mail::new().from("Brown@bar.com").to(&to).body("Blah");
+        client
+            .send(mail)
+            .expect("sent after the transaction was reset");
+    }
+}
+
+mod command {
+    use mailr_nal::{
+        io::{Read, Write},
+        message::Mail,
+        smtp::{Command, CommandError, Connection, SmtpClient},
+    };
+    use test_common::TestContext;
+
+    /// VRFY command (https://www.rfc-editor.org/rfc/rfc5321#section-4.1.1.6), returning the reply
+    /// code and text.
+    struct Vrfy<'a>(&'a str);
+
+    impl<S, B> Command<S, B> for Vrfy<'_>
+    where
+        S: Read + Write,
+        B: AsMut<[u8]>,
+    {
+        type Output = ([u8; 3], String);
+        type Error = CommandError<S::Error>;
+
+        fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error> {
+            stream.write_command(format_args!("VRFY {}", self.0))?;
+
+            let mut text = String::new();
+            let code = stream.read_reply(|line| text.push_str(line.text))?;
+            Ok((code, text))
+        }
+    }
+
+    /// A command the server doesn't know about.
+    struct Xfoo;
+
+    impl<S, B> Command<S, B> for Xfoo
+    where
+        S: Read + Write,
+        B: AsMut<[u8]>,
+    {
+        type Output = ();
+        type Error = CommandError<S::Error>;
+
+        fn execute(self, stream: &mut Connection<S, B>) -> Result<Self::Output, Self::Error> {
+            stream.write_command(format_args!("XFOO"))?;
+            stream.expect_reply(b"250")
+        }
+    }
+
+    #[test]
+    fn execute() {
+        let TestContext { plain_port, .. } = TestContext::setup();
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let mut client = SmtpClient::new(&mut stack, &mut buf[..])
+            .connect(([127, 0, 0, 1], plain_port))
+            .expect("connected");
+
+        let (code, text) = client.execute(Vrfy("user")).expect("VRFY executed");
+        assert_eq!(&code, b"252");
+        assert!(!text.is_empty());
+
+        // the session is still usable afterwards
+        let to = ["receiver@localhost".into()];
+        let mail = Mail::new()
+            .from("sender@localhost")
+            .to(&to)
+            .subject("After VRFY")
+            .body("Hello");
+        client.send(mail).expect("sent");
+    }
+
+    #[test]
+    fn rejected() {
+        let TestContext { plain_port, .. } = TestContext::setup();
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let mut client = SmtpClient::new(&mut stack, &mut buf[..])
+            .connect(([127, 0, 0, 1], plain_port))
+            .expect("connected");
+
+        let result = client.execute(Xfoo);
+        assert!(matches!(result, Err(CommandError::Rejected(code)) if &code == b"500"));
+
+        let (code, _) = client.execute(Vrfy("user")).expect("VRFY executed");
+        assert_eq!(&code, b"252");
+    }
+}
+
+#[cfg(test)]
+mod lmtp {
+    use mailr_nal::{
+        message::{Envelope, Mail},
+        smtp::{DeliveryStatus, Protocol, SendError, SmtpClient},
+    };
+    use test_common::TestContext;
+
+    #[test]
+    fn connect() {
+        let TestContext { lmtp_port, .. } = TestContext::setup();
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let _client = SmtpClient::new(&mut stack, &mut buf[..])
+            .with_protocol(Protocol::Lmtp)
+            .connect(([127, 0, 0, 1], lmtp_port))
+            .expect("connected with LHLO");
+    }
+
+    #[test]
+    fn send_with_status() {
+        let TestContext { lmtp_port, .. } = TestContext::setup();
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let mut client = SmtpClient::new(&mut stack, &mut buf[..])
+            .with_protocol(Protocol::Lmtp)
+            .connect(([127, 0, 0, 1], lmtp_port))
+            .unwrap();
+
+        let to = ["Jones@foo.com".into(), "nobody@foo.com".into()];
+        let cc = ["Green@foo.com".into()];
+        let mail = Mail::new()
+            .from("Smith@bar.com")
+            .to(&to)
+            .cc(&cc)
+            .subject("Test mail")
+            .body("Blah blah blah...\r\n..etc. etc. etc.");
+
+        let mut statuses = [None; 3];
+        client
+            .send_with_status(mail.clone(), |i, status| statuses[i] = Some(status))
+            .expect("sent successfully");
+
+        assert_eq!(
+            statuses,
+            [
+                Some(DeliveryStatus::Delivered),
+                Some(DeliveryStatus::Failed(*b"550")),
+                Some(DeliveryStatus::Delivered),
+            ]
+        );
+
+        let mail = mail.to(&to[..1]).cc(None);
+        client
+            .send(mail)
+            .expect("session is still usable after per-recipient failures");
+    }
+
+    #[test]
+    fn rejected_recipients() {
+        let TestContext { lmtp_port, .. } = TestContext::setup();
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let mut client = SmtpClient::new(&mut stack, &mut buf[..])
+            .with_protocol(Protocol::Lmtp)
+            .connect(([127, 0, 0, 1], lmtp_port))
+            .unwrap();
+
+        let envelope = Envelope::new(
+            "Smith@bar.com",
+            ["reject@foo.com", "Jones@foo.com", "nobody@foo.com"],
+        );
+        let mut statuses = [None; 3];
+        client
+            .send_raw_with_status(envelope, "Subject: LMTP\r\n\r\nBlah", |i, status| {
+                statuses[i] = Some(status)
+            })
+            .expect("sent to the accepted recipients");
+
+        assert_eq!(
+            statuses,
+            [
+                Some(DeliveryStatus::Failed(*b"550")),
+                Some(DeliveryStatus::Delivered),
+                Some(DeliveryStatus::Failed(*b"550")),
+            ]
+        );
+
+        let envelope = Envelope::new("Smith@bar.com", ["reject@foo.com"]);
+        let result = client.send_raw(envelope, "Subject: LMTP\r\n\r\nBlah");
+        assert!(matches!(result, Err(SendError::SendFailed)));
+
+        let envelope = Envelope::new("Smith@bar.com", ["Jones@foo.com"]);
+        client
+            .send_raw(envelope, "Subject: LMTP\r\n\r\nBlah")
+            .expect("transaction was reset");
+    }
+
+    #[test]
+    fn many_rejected_recipients() {
+        let TestContext { lmtp_port, .. } = TestContext::setup();
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let mut client = SmtpClient::new(&mut stack, &mut buf[..])
+            .with_protocol(Protocol::Lmtp)
+            .connect(([127, 0, 0, 1], lmtp_port))
+            .unwrap();
+
+        // every other recipient rejected
+        let receivers: Vec<_> = (0..100)
+            .map(|i| match i % 2 {
+                0 => format!("reject{i}@foo.com"),
+                _ => format!("Jones{i}@foo.com"),
+            })
+            .collect();
+        let envelope = Envelope::new("Smith@bar.com", receivers.iter());
+        let mut statuses = vec![None; receivers.len()];
+        client
+            .send_raw_with_status(envelope, "Subject: LMTP\r\n\r\nBlah", |i, status| {
+                statuses[i] = Some(status)
+            })
+            .expect("sent to the accepted recipients");
+
+        for (i, status) in statuses.into_iter().enumerate() {
+            let expected = match i % 2 {
+                0 => DeliveryStatus::Failed(*b"550"),
+                _ => DeliveryStatus::Delivered,
+            };
+            assert_eq!(status, Some(expected), "{}", receivers[i]);
+        }
+    }
+
+    #[test]
+    fn send_fails_on_any_rejection() {
+        let TestContext { lmtp_port, .. } = TestContext::setup();
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let mut client = SmtpClient::new(&mut stack, &mut buf[..])
+            .with_protocol(Protocol::Lmtp)
+            .connect(([127, 0, 0, 1], lmtp_port))
+            .unwrap();
+
+        let envelope = Envelope::new("Smith@bar.com", ["Jones@foo.com", "nobody@foo.com"]);
+        let result = client.send_raw(envelope, "Subject: LMTP\r\n\r\nBlah blah blah...");
+
+        assert!(
+            matches!(result, Err(SendError::SendFailed)),
+            "Send should fail if delivery to any recipient fails. Got: {:?}",
+            result,
+        );
+
+        let envelope = Envelope::new("Smith@bar.com", ["Jones@foo.com"]);
+        client
+            .send_raw(envelope, "Subject: LMTP\r\n\r\nBlah blah blah...")
+            .expect("remaining replies were consumed");
+    }
+}
+
+#[cfg(test)]
+mod mx {
+    use mailr_nal::{
+        date::{Clock, DateTime},
+        dns::{MxResolver, ResolveError, MAX_UDP_MESSAGE_LEN},
+        message::{InputError, Mail, MessageIdGenerator},
+        smtp::{ClientId, MxDelivery, MxDeliveryError, SendError},
+    };
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, UdpSocket},
+        sync::mpsc::{self, Receiver},
+        thread,
+        time::{SystemTime, UNIX_EPOCH},
+    };
+    use test_common::TestContext;
+
+    struct SystemClock;
+
+    impl Clock for SystemClock {
+        fn now(&self) -> DateTime {
+            let secs = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            DateTime::utc(secs as i64)
+        }
+    }
+
+    /// Stand-in DNS server answering `queries` queries with the given MX records, or NXDOMAIN if
+    /// there are none. Returns the port it listens on.
+    fn spawn_dns(queries: usize, records: &'static [(u16, &'static str)]) -> u16 {
+        spawn_lossy_dns(0, queries, records)
+    }
+
+    /// Same as `spawn_dns`, but drops the first `lost` queries.
+    fn spawn_lossy_dns(
+        lost: usize,
+        queries: usize,
+        records: &'static [(u16, &'static str)],
+    ) -> u16 {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+
+        thread::spawn(move || {
+            for i in 0..lost + queries {
+                let mut query = [0; 512];
+                let (n, remote) = socket.recv_from(&mut query).unwrap();
+                let query = &query[..n];
+                if i < lost {
+                    continue;
+                }
+
+                let rcode = if records.is_empty() { 3 } else { 0 };
+
+                let mut response = query[..2].to_vec();
+                response.extend_from_slice(&[0x81, 0x80 | rcode, 0, 1]);
+                response.extend_from_slice(&(records.len() as u16).to_be_bytes());
+                response.extend_from_slice(&[0, 0, 0, 0]);
+                response.extend_from_slice(&query[12..]);
+
+                for &(preference, exchange) in records {
+                    let mut rdata = preference.to_be_bytes().to_vec();
+                    for label in exchange.split('.') {
+                        rdata.push(label.len() as u8);
+                        rdata.extend_from_slice(label.as_bytes());
+                    }
+                    rdata.push(0);
+
+                    response.extend_from_slice(&[0xc0, 12, 0, 15, 0, 1, 0, 0, 1, 0]);
+                    response.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+                    response.extend_from_slice(&rdata);
+                }
+
+                socket.send_to(&response, remote).unwrap();
+            }
+        });
+
+        port
+    }
+
+    #[test]
+    fn resolve() {
+        let dns_port = spawn_dns(1, &[(20, "mx2.foo.com"), (10, "mx1.foo.com")]);
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; MAX_UDP_MESSAGE_LEN];
+
+        let records = MxResolver::new(&mut stack, ([127, 0, 0, 1], dns_port))
+            .with_query_id(0xbeef)
+            .resolve::<4>("foo.com", &mut buf)
+            .expect("resolved");
+
+        let records: Vec<_> = records
+            .iter()
+            .map(|r| (r.preference, r.exchange.as_str()))
+            .collect();
+        assert_eq!(records, [(10, "mx1.foo.com"), (20, "mx2.foo.com")]);
+    }
+
+    #[test]
+    fn resolve_retries() {
+        let dns_port = spawn_lossy_dns(1, 1, &[(10, "mx1.foo.com")]);
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut buf = [0; MAX_UDP_MESSAGE_LEN];
+
+        let records = MxResolver::new(&mut stack, ([127, 0, 0, 1], dns_port))
+            .with_timeout(&SystemClock, 1)
+            .resolve::<4>("foo.com", &mut buf)
+            .expect("resolved after a retry");
+        assert_eq!(records[0].exchange, "mx1.foo.com");
+
+        let dns_port = spawn_lossy_dns(2, 0, &[]);
+        let result = MxResolver::new(&mut stack, ([127, 0, 0, 1], dns_port))
+            .with_timeout(&SystemClock, 1)
+            .with_retries(1)
+            .resolve::<4>("foo.com", &mut buf);
+        assert!(
+            matches!(result, Err(ResolveError::Timeout)),
+            "Got: {:?}",
+            result
+        );
+    }
+
+    /// Stand-in SMTP server on `addr`, replying `rcpt_reply` to every RCPT TO, and accepting
+    /// everything else. Returns the message data received.
+    fn spawn_exchanger(listener: TcpListener, rcpt_reply: &'static str) -> Receiver<String> {
+        let (messages, received) = mpsc::channel();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut stream = stream;
+                stream.write_all(b"220 mx ESMTP\r\n").unwrap();
+
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 0 {
+                    let reply = match &line.to_ascii_uppercase()[..4] {
+                        "RCPT" => rcpt_reply,
+                        "DATA" => {
+                            stream.write_all(b"354 Go ahead\r\n").unwrap();
+                            let mut data = String::new();
+                            while line != ".\r\n" {
+                                data.push_str(&line);
+                                line.clear();
+                                reader.read_line(&mut line).unwrap();
+                            }
+                            let _ = messages.send(data);
+                            "250 OK\r\n"
+                        }
+                        "QUIT" => break,
+                        _ => "250 OK\r\n",
+                    };
+                    stream.write_all(reply.as_bytes()).unwrap();
+                    line.clear();
+                }
+            }
+        });
+
+        received
+    }
+
+    #[test]
+    fn deliver_deferred() {
+        let busy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = busy.local_addr().unwrap().port();
+        let backup = TcpListener::bind(("127.0.0.2", port)).unwrap();
+        spawn_exchanger(busy, "451 4.3.0 Try again later\r\n");
+        spawn_exchanger(backup, "250 OK\r\n");
+
+        let dns_port = spawn_dns(2, &[(10, "127.0.0.1"), (20, "127.0.0.2")]);
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut udp = std_embedded_nal::Stack;
+        let mut dns = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let resolver = MxResolver::new(&mut udp, ([127, 0, 0, 1], dns_port));
+        let mut delivery = MxDelivery::new(&mut stack, &mut buf, resolver, &mut dns)
+            .with_client_id(ClientId::new("example.com"))
+            .with_port(port);
+
+        let to = ["Jones@foo.com".into()];
+        let mail = Mail::new().from("Smith@baz.net").to(&to).body("Blah");
+
+        let mut delivered = false;
+        delivery
+            .send(mail.clone(), |_, result| delivered = result.is_ok())
+            .expect("valid mail");
+        assert!(delivered, "Delivery should move on to the backup exchanger");
+
+        let dns_port = spawn_dns(1, &[(10, "127.0.0.1")]);
+        let resolver = MxResolver::new(&mut udp, ([127, 0, 0, 1], dns_port));
+        let mut delivery = MxDelivery::new(&mut stack, &mut buf, resolver, &mut dns)
+            .with_client_id(ClientId::new("example.com"))
+            .with_port(port);
+
+        let mut deferred = false;
+        delivery
+            .send(
+                mail.clone(),
+                |_, result| {
+                    deferred =
+                        matches!(result, Err(MxDeliveryError::Deferred(code)) if &code == b"451")
+                },
+            )
+            .expect("valid mail");
+        assert!(
+            deferred,
+            "Delivery should be deferred by the only exchanger"
+        );
+
+        let busy = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = busy.local_addr().unwrap().port();
+        spawn_exchanger(busy, "451 4.3.0 Try again later\r\n");
+
+        // nothing listens on the backup exchanger
+        let dns_port = spawn_dns(2, &[(10, "127.0.0.1"), (20, "127.0.0.3")]);
+        let resolver = MxResolver::new(&mut udp, ([127, 0, 0, 1], dns_port));
+        let mut delivery = MxDelivery::new(&mut stack, &mut buf, resolver, &mut dns)
+            .with_client_id(ClientId::new("example.com"))
+            .with_port(port);
+
+        let mut deferred = false;
+        delivery
+            .send(
+                mail,
+                |_, result| {
+                    deferred =
+                        matches!(result, Err(MxDeliveryError::Deferred(code)) if &code == b"451")
+                },
+            )
+            .expect("valid mail");
+        assert!(
+            deferred,
+            "Delivery should stay deferred when the backup exchanger is unreachable"
+        );
+    }
+
+    /// Not random at all, for predictable IDs.
+    struct CountingRng(u8);
+
+    impl rand_core::RngCore for CountingRng {
+        fn next_u32(&mut self) -> u32 {
+            rand_core::impls::next_u32_via_fill(self)
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            rand_core::impls::next_u64_via_fill(self)
+        }
+
+        fn fill_bytes(&mut self, dst: &mut [u8]) {
+            for byte in dst {
+                *byte = self.0;
+                self.0 = self.0.wrapping_add(1);
+            }
+        }
+    }
+
+    #[test]
+    fn deliver_prepared_mail() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let messages = spawn_exchanger(listener, "250 OK\r\n");
+
+        let dns_port = spawn_dns(1, &[(10, "127.0.0.1")]);
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut udp = std_embedded_nal::Stack;
+        let mut dns = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let resolver = MxResolver::new(&mut udp, ([127, 0, 0, 1], dns_port));
+        let mut delivery = MxDelivery::new(&mut stack, &mut buf, resolver, &mut dns)
+            .with_client_id(ClientId::new("example.com"))
+            .with_port(port);
+
+        // checked before anything is sent
+        let to = ["Jones@foo.com".into()];
+        let mail = Mail::new()
+            .from("Smith@baz.net")
+            .to(&to)
+            .subject("Hi\r\nBcc: eve@example.com");
+        let result = delivery.send(mail, |_, _| panic!("nothing should be sent"));
+        assert!(matches!(
+            result,
+            Err(SendError::InvalidInput(InputError::HeaderValue))
+        ));
+
+        let message_ids =
+            MessageIdGenerator::new(CountingRng(0), ClientId::new("example.com"), &SystemClock);
+        let bcc = ["Jones@foo.com".into()];
+        let mail = Mail::new()
+            .from("Smith@baz.net")
+            .bcc(&bcc)
+            .message_id(&message_ids)
+            .body("Blah");
+
+        let mut delivered = false;
+        let message_id = delivery
+            .send(mail, |_, result| delivered = result.is_ok())
+            .expect("valid mail")
+            .expect("generated");
+        assert!(delivered);
+
+        let data = messages.recv().unwrap();
+        assert!(data.contains("To:undisclosed-recipients:;\r\n"), "{data}");
+        assert!(
+            data.contains(&format!("Message-ID:{}\r\n", message_id)),
+            "{data}"
+        );
+    }
+
+    #[test]
+    fn deliver_by_domain() {
+        let TestContext { plain_port, .. } = TestContext::setup();
+        let dns_port = spawn_dns(2, &[(20, "localhost"), (10, "unreachable.invalid")]);
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut udp = std_embedded_nal::Stack;
+        let mut dns = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let resolver = MxResolver::new(&mut udp, ([127, 0, 0, 1], dns_port));
+        let mut delivery = MxDelivery::new(&mut stack, &mut buf, resolver, &mut dns)
+            .with_client_id(ClientId::new("example.com"))
+            .with_port(plain_port);
+
+        let to = ["Jones@foo.com".into(), "Green@bar.org".into()];
+        let cc = ["John@FOO.com".into()];
+        let bcc = ["Brown@foo.com".into()];
+        let mail = Mail::new()
+            .from("Smith@baz.net")
+            .to(&to)
+            .cc(&cc)
+            .bcc(&bcc)
+            .subject("Direct delivery")
+            .body("Blah blah blah...");
+
+        let mut results = Vec::new();
+        delivery
+            .send(mail.clone(), |domain, result| {
+                results.push((domain.to_owned(), format!("{:?}", result)))
+            })
+            .expect("valid mail");
+
+        assert_eq!(
+            results,
+            [
+                ("foo.com".to_owned(), "Ok(())".to_owned()),
+                ("bar.org".to_owned(), "Ok(())".to_owned()),
+            ]
+        );
+
+        // an invalid recipient is caught before any domain is delivered to
+        let bcc = ["Brown".into()];
+        let mut results = Vec::new();
+        let result = delivery.send(mail.bcc(&bcc), |domain, _| results.push(domain.to_owned()));
+        assert!(matches!(
+            result,
+            Err(SendError::InvalidInput(InputError::Address))
+        ));
+        assert!(results.is_empty());
+    }
+
+    #[test]
+    fn no_such_domain() {
+        let dns_port = spawn_dns(1, &[]);
+
+        let mut stack = std_embedded_nal::Stack;
+        let mut udp = std_embedded_nal::Stack;
+        let mut dns = std_embedded_nal::Stack;
+        let mut buf = [0; 1024];
+
+        let resolver = MxResolver::new(&mut udp, ([127, 0, 0, 1], dns_port));
+        let mut delivery = MxDelivery::new(&mut stack, &mut buf, resolver, &mut dns);
+
+        let to = ["Jones@foo.invalid".into()];
+        let mail = Mail::new().from("Smith@baz.net").to(&to).body("Blah");
+
+        let mut failed = false;
+        delivery
+            .send(mail, |_, result| {
+                failed = matches!(
+                    result,
+                    Err(MxDeliveryError::ResolveError(ResolveError::NoSuchDomain))
+                )
+            })
+            .expect("valid mail");
+        assert!(failed, "Delivery should fail for a nonexistent domain");
+    }
+}
@@ -9,6 +9,7 @@ try:
     import logging
     from aiosmtpd.controller import UnthreadedController
     from aiosmtpd.handlers import Debugging
+    from aiosmtpd.lmtp import LMTP
     from aiosmtpd.smtp import AuthResult, LoginPassword
     import ssl
 except ModuleNotFoundError:
@@ -22,6 +23,9 @@ def print_usage():
 Env:
     DEBUG       Enable debug log. Default 0.
     PORT        The port to listen on. Default 2525.
+    LMTP        Speak LMTP instead of SMTP. Default 0. Recipients with the local part "reject"
+                are rejected by RCPT, and delivery to those with the local part "nobody" is
+                rejected after DATA, to test per-recipient replies.
 
     CERT        The certificate file to use for TLS. Must be defined together with `KEY` to enable TLS.
     KEY         The key file to use for TLS. Must be defined together with `CERT` to enable TLS.
@@ -34,6 +38,28 @@ Env:
     print(usage)
 
 
+class LmtpDebugging(Debugging):
+    """Debugging handler that replies once per recipient after DATA, as LMTP requires."""
+
+    async def handle_RCPT(self, server, session, envelope, address, rcpt_options):
+        if address.startswith("reject@"):
+            return "550 5.1.1 Mailbox unavailable"
+        envelope.rcpt_tos.append(address)
+        return "250 OK"
+
+    async def handle_DATA(self, server, session, envelope):
+        await super().handle_DATA(server, session, envelope)
+        return "\r\n".join(
+            "550 5.1.1 Mailbox unavailable" if rcpt.startswith("nobody@") else "250 OK"
+            for rcpt in envelope.rcpt_tos
+        )
+
+
+class LmtpController(UnthreadedController):
+    def factory(self):
+        return LMTP(self.handler, **self.SMTP_kwargs)
+
+
 def main():
     if len(sys.argv) > 1:
         print_usage()
@@ -85,8 +111,9 @@ def main():
     # Create SMTP server
     HOST = ""  # any interface
     PORT = int(os.getenv("PORT", 2525))
-    controller = UnthreadedController(
-        Debugging(),
+    lmtp = os.getenv("LMTP", "0") != "0"
+    controller = (LmtpController if lmtp else UnthreadedController)(
+        LmtpDebugging() if lmtp else Debugging(),
         hostname=HOST,
         port=PORT,
         loop=loop,